@@ -1,8 +1,6 @@
-mod network;
-
 use std::net::Ipv4Addr;
 
-use network::NetworkTun;
+use yttria_nexus::network::NetworkTun;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut tun = NetworkTun::builder()
@@ -0,0 +1,303 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    os::fd::{AsRawFd as _, OwnedFd},
+};
+
+use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+
+use super::{
+    consts,
+    error::NetworkError,
+    flags::InterfaceFlags,
+    mac::MacAddr,
+    settings::{prefix_to_mask, validate_name, IfReq, NetworkArpEntry},
+};
+
+/// Interface configuration operations shared by the ioctl and netlink backends.
+///
+/// Every operation addresses the interface by name so a single backend can be shared between
+/// multiple interfaces.
+pub trait NetworkBackend: Send + Sync {
+    fn flags(&self, name: &str) -> Result<InterfaceFlags, NetworkError>;
+
+    /// Writes `flags` to the interface. Read-only flags such as `RUNNING` are ignored.
+    fn set_flags(&self, name: &str, flags: InterfaceFlags) -> Result<(), NetworkError>;
+
+    fn add_address(&self, name: &str, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError>;
+
+    /// Adds `address` with the mask the kernel picks when none is given, which is /32 on
+    /// point-to-point links such as tuns and the classful mask otherwise. Returns its prefix
+    /// length.
+    fn add_address_default_mask(&self, name: &str, address: Ipv4Addr) -> Result<u8, NetworkError>;
+
+    fn delete_address(
+        &self,
+        name: &str,
+        address: IpAddr,
+        prefix_len: u8,
+    ) -> Result<(), NetworkError>;
+
+    fn mtu(&self, name: &str) -> Result<u32, NetworkError>;
+
+    fn set_mtu(&self, name: &str, mtu: u32) -> Result<(), NetworkError>;
+
+    /// Length of the queue of packets waiting for us to read them, in packets.
+    fn tx_queue_len(&self, name: &str) -> Result<u32, NetworkError>;
+
+    fn set_tx_queue_len(&self, name: &str, len: u32) -> Result<(), NetworkError>;
+
+    /// The Ethernet address of a tap device.
+    fn hardware_address(&self, name: &str) -> Result<MacAddr, NetworkError>;
+
+    fn set_hardware_address(&self, name: &str, address: MacAddr) -> Result<(), NetworkError>;
+
+    /// Renames the interface, which must be down.
+    fn rename(&self, name: &str, new_name: &str) -> Result<(), NetworkError>;
+
+    /// The interface's description, empty if none is set.
+    fn alias(&self, name: &str) -> Result<String, NetworkError>;
+
+    /// Sets the interface's description, or clears it if `alias` is empty.
+    fn set_alias(&self, name: &str, alias: &str) -> Result<(), NetworkError>;
+
+    /// Adds or replaces a permanent neighbor entry resolving `address` to `mac`.
+    fn add_neighbor(&self, name: &str, address: IpAddr, mac: MacAddr) -> Result<(), NetworkError>;
+
+    /// The hardware address `address` resolves to, if the neighbor table has a usable entry.
+    fn neighbor(&self, name: &str, address: IpAddr) -> Result<Option<MacAddr>, NetworkError>;
+
+    fn delete_neighbor(&self, name: &str, address: IpAddr) -> Result<(), NetworkError>;
+}
+
+/// Longest description the kernel accepts, `IFALIASZ` less the terminator.
+pub(crate) const MAX_ALIAS_LEN: usize = 255;
+
+pub(crate) fn validate_alias(alias: &str) -> Result<(), NetworkError> {
+    if alias.len() > MAX_ALIAS_LEN || alias.contains(['\n', '\0']) {
+        return Err(NetworkError::InvalidArgument(format!(
+            "Interface alias must be a single line of at most {MAX_ALIAS_LEN} bytes (got '{alias}')"
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_prefix_len(address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+    let max = match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    if prefix_len == 0 || prefix_len > max {
+        return Err(NetworkError::InvalidSubnetMask(format!(
+            "Subnet mask value is invalid (got '{prefix_len}')"
+        )));
+    }
+    Ok(())
+}
+
+/// Configures interfaces through the legacy `SIOCS*` ioctls.
+///
+/// Only a single Ipv4 address can be assigned per interface, and Ipv6 is not supported.
+pub struct IoctlBackend {
+    sock_fd: OwnedFd,
+}
+
+impl IoctlBackend {
+    pub fn new() -> Result<Self, NetworkError> {
+        let sock_fd = socket(
+            AddressFamily::Inet,
+            SockType::Datagram,
+            SockFlag::empty(),
+            None,
+        )
+        .map_err(NetworkError::call("ioctl socket"))?;
+
+        Ok(Self { sock_fd })
+    }
+
+    fn set_ipv4_address(&self, name: &str, address: Ipv4Addr) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_ipv4(address);
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFADDR, "SIOCSIFADDR"))
+    }
+
+    fn arp_ioctl(
+        &self,
+        name: &str,
+        (request, operation): (u64, &str),
+        entry: &mut NetworkArpEntry,
+    ) -> Result<(), NetworkError> {
+        let code =
+            unsafe { nix::libc::ioctl(self.sock_fd.as_raw_fd(), request, entry.as_mut_ptr()) };
+        nix::errno::Errno::result(code).map_err(NetworkError::on(operation, name))?;
+
+        Ok(())
+    }
+
+    fn set_subnet_mask(&self, name: &str, prefix_len: u8) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_ipv4(Ipv4Addr::from(prefix_to_mask(prefix_len)));
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFNETMASK, "SIOCSIFNETMASK"))
+    }
+}
+
+fn ipv4_neighbor(address: IpAddr) -> Result<Ipv4Addr, NetworkError> {
+    match address {
+        IpAddr::V4(address) => Ok(address),
+        IpAddr::V6(_) => Err(NetworkError::Unsupported(
+            "Ipv6 neighbors require the netlink backend".to_string(),
+        )),
+    }
+}
+
+impl NetworkBackend for IoctlBackend {
+    fn flags(&self, name: &str) -> Result<InterfaceFlags, NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCGIFFLAGS, "SIOCGIFFLAGS"))?;
+        let mut flags = InterfaceFlags::from_ifr_flags(ifreq.flags());
+
+        // LOWER_UP doesn't fit in ifr_flags, so it comes from ethtool instead. Like the kernel we
+        // only report it while the interface is up, and drivers without ethtool never have it
+        if flags.contains(InterfaceFlags::UP) && ifreq.ethtool_link(&self.sock_fd).unwrap_or(false)
+        {
+            flags.insert(InterfaceFlags::LOWER_UP);
+        }
+
+        Ok(flags)
+    }
+
+    fn set_flags(&self, name: &str, flags: InterfaceFlags) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_flags(flags.difference(InterfaceFlags::READ_ONLY).to_ifr_flags());
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFFLAGS, "SIOCSIFFLAGS"))
+    }
+
+    /// Replaces the interface's Ipv4 address, as the ioctl interface only supports one.
+    fn add_address(&self, name: &str, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+        validate_prefix_len(address, prefix_len)?;
+
+        match address {
+            IpAddr::V4(address) => {
+                self.set_ipv4_address(name, address)?;
+                self.set_subnet_mask(name, prefix_len)
+            }
+            IpAddr::V6(_) => Err(NetworkError::Unsupported(
+                "Ipv6 addresses require the netlink backend".to_string(),
+            )),
+        }
+    }
+
+    /// Leaves the mask to `SIOCSIFADDR`, which is how the kernel default is picked.
+    fn add_address_default_mask(&self, name: &str, address: Ipv4Addr) -> Result<u8, NetworkError> {
+        self.set_ipv4_address(name, address)?;
+
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCGIFNETMASK, "SIOCGIFNETMASK"))?;
+        Ok(u32::from(ifreq.ipv4()).count_ones() as u8)
+    }
+
+    /// Clears the interface's Ipv4 address, `prefix_len` is ignored.
+    fn delete_address(
+        &self,
+        name: &str,
+        address: IpAddr,
+        _prefix_len: u8,
+    ) -> Result<(), NetworkError> {
+        match address {
+            IpAddr::V4(_) => self.set_ipv4_address(name, Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => Err(NetworkError::Unsupported(
+                "Ipv6 addresses require the netlink backend".to_string(),
+            )),
+        }
+    }
+
+    fn mtu(&self, name: &str) -> Result<u32, NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCGIFMTU, "SIOCGIFMTU"))?;
+        Ok(ifreq.value())
+    }
+
+    fn set_mtu(&self, name: &str, mtu: u32) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_value(mtu);
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFMTU, "SIOCSIFMTU"))
+    }
+
+    fn tx_queue_len(&self, name: &str) -> Result<u32, NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCGIFTXQLEN, "SIOCGIFTXQLEN"))?;
+        Ok(ifreq.value())
+    }
+
+    fn set_tx_queue_len(&self, name: &str, len: u32) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_value(len);
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFTXQLEN, "SIOCSIFTXQLEN"))
+    }
+
+    fn hardware_address(&self, name: &str) -> Result<MacAddr, NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCGIFHWADDR, "SIOCGIFHWADDR"))?;
+        Ok(ifreq.hardware_address())
+    }
+
+    fn set_hardware_address(&self, name: &str, address: MacAddr) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_hardware_address(nix::libc::ARPHRD_ETHER, address);
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFHWADDR, "SIOCSIFHWADDR"))
+    }
+
+    fn add_neighbor(&self, name: &str, address: IpAddr, mac: MacAddr) -> Result<(), NetworkError> {
+        let mut entry = NetworkArpEntry::new(name, ipv4_neighbor(address)?, mac)?;
+        self.arp_ioctl(name, (consts::SIOCSARP, "SIOCSARP"), &mut entry)
+    }
+
+    fn neighbor(&self, name: &str, address: IpAddr) -> Result<Option<MacAddr>, NetworkError> {
+        let mut entry = NetworkArpEntry::blank(name, ipv4_neighbor(address)?)?;
+        match self.arp_ioctl(name, (consts::SIOCGARP, "SIOCGARP"), &mut entry) {
+            Ok(()) => Ok(entry.address()),
+            // The kernel reports a missing entry as ENXIO
+            Err(e) if e.errno() == Some(nix::errno::Errno::ENXIO) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn delete_neighbor(&self, name: &str, address: IpAddr) -> Result<(), NetworkError> {
+        let mut entry = NetworkArpEntry::blank(name, ipv4_neighbor(address)?)?;
+        self.arp_ioctl(name, (consts::SIOCDARP, "SIOCDARP"), &mut entry)
+    }
+
+    fn rename(&self, name: &str, new_name: &str) -> Result<(), NetworkError> {
+        let mut ifreq = IfReq::new(name)?;
+        ifreq.set_new_name(new_name)?;
+        ifreq.ioctl(&self.sock_fd, (consts::SIOCSIFNAME, "SIOCSIFNAME"))
+    }
+
+    // There is no ioctl for the alias, so it goes through sysfs instead
+    fn alias(&self, name: &str) -> Result<String, NetworkError> {
+        let path = sysfs_alias(name)?;
+        let alias = std::fs::read_to_string(&path)
+            .map_err(NetworkError::on(&format!("reading {path}"), name))?;
+
+        Ok(alias.trim_end_matches('\n').to_owned())
+    }
+
+    fn set_alias(&self, name: &str, alias: &str) -> Result<(), NetworkError> {
+        validate_alias(alias)?;
+        let path = sysfs_alias(name)?;
+        // Writing just a newline clears the alias, as an empty write is ignored
+        std::fs::write(&path, format!("{alias}\n"))
+            .map_err(NetworkError::on(&format!("writing {path}"), name))
+    }
+}
+
+/// Sysfs shows the namespace it was mounted in, so it would find a different interface with the
+/// same name rather than one in the namespace we switched to.
+fn sysfs_alias(name: &str) -> Result<String, NetworkError> {
+    if super::netns::entered() {
+        return Err(NetworkError::Unsupported(
+            "Aliases of interfaces in another namespace require the netlink backend".to_string(),
+        ));
+    }
+    validate_name(name)?;
+    Ok(format!("/sys/class/net/{name}/ifalias"))
+}
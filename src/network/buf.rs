@@ -0,0 +1,125 @@
+use super::{error::NetworkError, packet::IpPacket};
+
+/// A fixed capacity buffer holding a single raw Ip packet.
+///
+/// Used with [`NetworkTun::recv_batch`](super::NetworkTun::recv_batch) and
+/// [`NetworkTun::send_batch`](super::NetworkTun::send_batch) to move packets without parsing
+/// or allocating for each one.
+#[derive(Debug, Clone)]
+pub struct PacketBuf {
+    data: Box<[u8]>,
+    len: usize,
+}
+
+impl PacketBuf {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: vec![0; capacity].into_boxed_slice(),
+            len: 0,
+        }
+    }
+
+    pub fn from_slice(data: &[u8]) -> Self {
+        Self {
+            data: data.into(),
+            len: data.len(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Replaces the contents of the buffer with `data`.
+    pub fn fill(&mut self, data: &[u8]) -> Result<(), NetworkError> {
+        if data.len() > self.capacity() {
+            return Err(NetworkError::PacketParseError(format!(
+                "packet does not fit in buffer (packet: {}, capacity: {})",
+                data.len(),
+                self.capacity()
+            )));
+        }
+        self.data[..data.len()].copy_from_slice(data);
+        self.len = data.len();
+        Ok(())
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    pub fn parse(&self) -> Result<IpPacket, NetworkError> {
+        IpPacket::from_bytes(self.as_slice())
+    }
+
+    pub(crate) fn spare_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub(crate) fn set_len(&mut self, len: usize) {
+        self.len = len.min(self.capacity());
+    }
+}
+
+impl std::ops::Deref for PacketBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl From<&IpPacket> for PacketBuf {
+    fn from(packet: &IpPacket) -> Self {
+        Self::from_slice(&packet.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_replaces_the_contents() {
+        let mut buf = PacketBuf::new(4);
+        assert!(buf.is_empty());
+
+        buf.fill(&[1, 2, 3]).unwrap();
+        assert_eq!(buf.as_slice(), [1, 2, 3]);
+        buf.fill(&[4]).unwrap();
+        assert_eq!(buf.as_slice(), [4]);
+
+        buf.clear();
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), 4);
+    }
+
+    #[test]
+    fn capacity_is_never_exceeded() {
+        let mut buf = PacketBuf::from_slice(&[1, 2]);
+        assert_eq!((buf.len(), buf.capacity()), (2, 2));
+
+        assert!(buf.fill(&[0; 3]).is_err());
+        assert_eq!(
+            buf.as_slice(),
+            [1, 2],
+            "failed fills leave the buffer alone"
+        );
+
+        buf.set_len(5);
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf.spare_mut().len(), 2);
+    }
+}
@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use nix::{request_code_read, request_code_write};
+use nix::{request_code_none, request_code_read, request_code_write};
 use std::ffi::{c_int, c_uint};
 use std::mem::size_of;
 
@@ -17,13 +17,13 @@ pub const TUNSETTXFILTER: u64 = request_code_write!('T', 209, size_of::<c_uint>(
 pub const TUNGETIFF: u64 = request_code_read!('T', 210, size_of::<c_uint>());
 pub const TUNGETSNDBUF: u64 = request_code_read!('T', 211, size_of::<c_int>());
 pub const TUNSETSNDBUF: u64 = request_code_write!('T', 212, size_of::<c_int>());
-// pub const TUNATTACHFILTER: u64 = request_code_write!('T', 213, struct sock_fprog);
-// pub const TUNDETACHFILTER: u64 = request_code_write!('T', 214, struct sock_fprog);
+pub const TUNATTACHFILTER: u64 = request_code_write!('T', 213, size_of::<nix::libc::sock_fprog>());
+pub const TUNDETACHFILTER: u64 = request_code_write!('T', 214, size_of::<nix::libc::sock_fprog>());
 pub const TUNGETVNETHDRSZ: u64 = request_code_read!('T', 215, size_of::<c_int>());
 pub const TUNSETVNETHDRSZ: u64 = request_code_write!('T', 216, size_of::<c_int>());
 pub const TUNSETQUEUE: u64 = request_code_write!('T', 217, size_of::<c_int>());
 pub const TUNSETIFINDEX: u64 = request_code_write!('T', 218, size_of::<c_uint>());
-// pub const TUNGETFILTER: u64 = request_code_read!('T', 219, struct sock_fprog);
+pub const TUNGETFILTER: u64 = request_code_read!('T', 219, size_of::<nix::libc::sock_fprog>());
 pub const TUNSETVNETLE: u64 = request_code_write!('T', 220, size_of::<c_int>());
 pub const TUNGETVNETLE: u64 = request_code_read!('T', 221, size_of::<c_int>());
 /* The TUNSETVNETBE and TUNGETVNETBE ioctls are for cross-endian support on
@@ -34,7 +34,7 @@ pub const TUNGETVNETLE: u64 = request_code_read!('T', 221, size_of::<c_int>());
 // pub const TUNGETVNETBE request_code_read!('T', 223, int)
 // pub const TUNSETSTEERINGEBPF request_code_read!('T', 224, int)
 // pub const TUNSETFILTEREBPF request_code_read!('T', 225, int)
-// pub const TUNSETCARRIER request_code_write!('T', 226, int)
+pub const TUNSETCARRIER: u64 = request_code_write!('T', 226, size_of::<c_int>());
 // pub const TUNGETDEVNETNS _IO('T', 227)
 
 /* TUNSETIFF ifr flags */
@@ -59,6 +59,16 @@ pub const IFF_DETACH_QUEUE: u16 = 0x0400;
 pub const IFF_PERSIST: u16 = 0x0800;
 pub const IFF_NOFILTER: u16 = 0x1000;
 
+/* TUNSETTXFILTER flags */
+pub const TUN_FLT_ALLMULTI: u16 = 0x0001;
+
+/* Packet information header flags */
+pub const TUN_PKT_STRIP: u16 = 0x0001;
+
+/* Protocols carried in the tun packet information header */
+pub const ETH_P_IP: u16 = 0x0800;
+pub const ETH_P_IPV6: u16 = 0x86DD;
+
 /* Socket options */
 pub const TUN_TX_TIMESTAMP: u16 = 1;
 
@@ -119,12 +129,22 @@ pub const SIOCSIFBR: u64 = 0x8941; /* Set bridging options */
 pub const SIOCGIFTXQLEN: u64 = 0x8942; /* Get the tx queue length */
 pub const SIOCSIFTXQLEN: u64 = 0x8943; /* Set the tx queue length */
 
+pub const SIOCETHTOOL: u64 = 0x8946; /* Ethtool interface */
+
+/* Ethtool commands, passed at the start of the SIOCETHTOOL argument. */
+pub const ETHTOOL_GDRVINFO: u32 = 0x00000003; /* Get driver info (ethtool_drvinfo) */
+pub const ETHTOOL_GLINK: u32 = 0x0000000a; /* Get link status (ethtool_value) */
+
 /* ARP cache control calls. */
 /* 0x8950 - 0x8952 * obsolete calls, don't re-use */
 pub const SIOCDARP: u64 = 0x8953; /* delete ARP table entry */
 pub const SIOCGARP: u64 = 0x8954; /* get ARP table entry */
 pub const SIOCSARP: u64 = 0x8955; /* set ARP table entry */
 
+/* ARP Flag values. */
+pub const ATF_COM: i32 = 0x02; /* completed entry (ha valid) */
+pub const ATF_PERM: i32 = 0x04; /* permanent entry */
+
 /* RARP cache control calls. */
 pub const SIOCDRARP: u64 = 0x8960; /* delete RARP table entry */
 pub const SIOCGRARP: u64 = 0x8961; /* get RARP table entry */
@@ -176,3 +196,15 @@ pub const IFF_MULTICAST: u16 = 0x1000; /* Supports multicast. */
 pub const IFF_PORTSEL: u16 = 0x2000; /* Can set media type. */
 pub const IFF_AUTOMEDIA: u16 = 0x4000; /* Auto media select active. */
 pub const IFF_DYNAMIC: u16 = 0x8000; /* Dialup device with changing addresses. */
+
+/* Only reported through netlink or sysfs, these do not fit in ifr_flags */
+pub const IFF_LOWER_UP: u32 = 0x10000; /* Driver signals L1 up. */
+pub const IFF_DORMANT: u32 = 0x20000; /* Driver signals dormant. */
+pub const IFF_ECHO: u32 = 0x40000; /* Echo sent packets. */
+
+/* Namespace ioctls, linux/nsfs.h */
+pub const NS_GET_USERNS: u64 = request_code_none!(0xb7, 0x1);
+pub const NS_GET_PARENT: u64 = request_code_none!(0xb7, 0x2);
+
+/* Capability bits, linux/capability.h */
+pub const CAP_NET_ADMIN: u32 = 12;
@@ -2,17 +2,131 @@ use nix::errno::Errno;
 
 #[derive(Debug)]
 pub enum NetworkError {
-    IoError(String),
+    /// A system call or file operation failed.
+    Os {
+        /// What was being done, such as the ioctl or netlink message sent.
+        operation: String,
+        /// The interface it was done on, where there is one.
+        interface: Option<String>,
+        source: std::io::Error,
+    },
+    /// An operation was refused, along with what [`Preflight`](super::preflight::Preflight)
+    /// found to be missing.
+    PermissionDenied {
+        diagnosis: String,
+        source: Box<NetworkError>,
+    },
+    /// The kernel reported something we couldn't make sense of.
+    InvalidResponse(String),
     InvalidName(String),
     InvalidSubnetMask(String),
-    IoctlError(Errno),
+    InvalidRoute(String),
+    InvalidMacAddress(String),
+    /// A value was rejected before reaching the kernel, such as an interface alias.
+    InvalidArgument(String),
     PacketParseError(String),
+    Unsupported(String),
+}
+
+impl NetworkError {
+    pub(crate) fn os(
+        operation: impl Into<String>,
+        interface: Option<&str>,
+        source: impl Into<std::io::Error>,
+    ) -> Self {
+        Self::Os {
+            operation: operation.into(),
+            interface: interface.map(str::to_owned),
+            source: source.into(),
+        }
+    }
+
+    /// For `map_err` on an operation on the interface called `interface`.
+    pub(crate) fn on<E: Into<std::io::Error>>(
+        operation: &str,
+        interface: &str,
+    ) -> impl FnOnce(E) -> Self {
+        let operation = operation.to_owned();
+        let interface = interface.to_owned();
+        move |source| Self::os(operation, Some(interface.as_str()), source)
+    }
+
+    /// For `map_err` on an operation which isn't about a single interface.
+    pub(crate) fn call<E: Into<std::io::Error>>(operation: &str) -> impl FnOnce(E) -> Self {
+        let operation = operation.to_owned();
+        move |source| Self::os(operation, None, source)
+    }
+
+    /// Fills in the interface on errors from operations which didn't know it.
+    pub(crate) fn with_interface(self, name: &str) -> Self {
+        match self {
+            Self::Os {
+                operation,
+                interface: None,
+                source,
+            } => Self::os(operation, Some(name), source),
+            other => other,
+        }
+    }
+
+    /// The errno a failed system call returned.
+    pub fn errno(&self) -> Option<Errno> {
+        match self {
+            Self::Os { source, .. } => source.raw_os_error().map(Errno::from_raw),
+            Self::PermissionDenied { source, .. } => source.errno(),
+            _ => None,
+        }
+    }
+
+    /// Whether we lack a capability such as `CAP_NET_ADMIN`, or access to a file.
+    pub fn is_permission_denied(&self) -> bool {
+        match self {
+            Self::Os { source, .. } => source.kind() == std::io::ErrorKind::PermissionDenied,
+            Self::PermissionDenied { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the operation failed for a transient reason and may succeed if tried again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.errno(),
+            Some(Errno::EAGAIN | Errno::EINTR | Errno::EBUSY | Errno::ENOBUFS)
+        )
+    }
 }
 
 impl std::fmt::Display for NetworkError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Network Error: {self:?}")
+        match self {
+            Self::Os {
+                operation,
+                interface: Some(interface),
+                source,
+            } => write!(f, "{operation} on {interface} failed: {source}"),
+            Self::Os {
+                operation, source, ..
+            } => write!(f, "{operation} failed: {source}"),
+            // The refused operation is left to `source()`, so reporters don't print it twice
+            Self::PermissionDenied { diagnosis, .. } => write!(f, "Permission denied: {diagnosis}"),
+            Self::InvalidResponse(message) => write!(f, "Unexpected response: {message}"),
+            Self::InvalidName(message)
+            | Self::InvalidSubnetMask(message)
+            | Self::InvalidRoute(message)
+            | Self::InvalidMacAddress(message)
+            | Self::InvalidArgument(message)
+            | Self::PacketParseError(message)
+            | Self::Unsupported(message) => f.write_str(message),
+        }
     }
 }
 
-impl std::error::Error for NetworkError {}
+impl std::error::Error for NetworkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Os { source, .. } => Some(source),
+            Self::PermissionDenied { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
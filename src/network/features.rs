@@ -0,0 +1,91 @@
+use std::os::fd::AsRawFd as _;
+
+use bitflags::bitflags;
+
+use super::{consts, error::NetworkError};
+
+bitflags! {
+    /// Device flags the running kernel accepts when creating a tun, as reported by
+    /// `TUNGETFEATURES`.
+    ///
+    /// Flags the kernel doesn't know about are silently ignored at creation, so check these
+    /// before relying on one.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct TunFeatures: u16 {
+        const TUN = consts::IFF_TUN;
+        const TAP = consts::IFF_TAP;
+        const NAPI = consts::IFF_NAPI;
+        const NAPI_FRAGS = consts::IFF_NAPI_FRAGS;
+        const NO_CARRIER = consts::IFF_NO_CARRIER;
+        const NO_PI = consts::IFF_NO_PI;
+        const ONE_QUEUE = consts::IFF_ONE_QUEUE;
+        const VNET_HDR = consts::IFF_VNET_HDR;
+        const MULTI_QUEUE = consts::IFF_MULTI_QUEUE;
+    }
+}
+
+impl TunFeatures {
+    /// Asks the running kernel which features it supports.
+    pub fn query() -> Result<Self, NetworkError> {
+        let tun_fd = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")
+            .map_err(NetworkError::call("opening /dev/net/tun"))?;
+
+        Self::from_device(&tun_fd)
+    }
+
+    pub(crate) fn from_device(tun_fd: &std::fs::File) -> Result<Self, NetworkError> {
+        let mut features: nix::libc::c_uint = 0;
+        let code = unsafe {
+            nix::libc::ioctl(
+                tun_fd.as_raw_fd(),
+                consts::TUNGETFEATURES,
+                &mut features as *mut nix::libc::c_uint,
+            )
+        };
+        nix::errno::Errno::result(code).map_err(NetworkError::call("TUNGETFEATURES"))?;
+
+        Ok(Self::from_bits_truncate(features as u16))
+    }
+
+    /// Fails with the features from `wanted` which the kernel doesn't support.
+    pub fn require(self, wanted: Self) -> Result<(), NetworkError> {
+        let missing = wanted.difference(self);
+        if !missing.is_empty() {
+            return Err(NetworkError::Unsupported(format!(
+                "The running kernel doesn't support the requested tun features (missing: {missing:?})"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_features_are_accepted() {
+        let supported = TunFeatures::TUN | TunFeatures::TAP | TunFeatures::NO_PI;
+        assert!(supported.require(TunFeatures::empty()).is_ok());
+        assert!(supported.require(TunFeatures::TAP).is_ok());
+        assert!(supported.require(supported).is_ok());
+    }
+
+    #[test]
+    fn missing_features_are_named() {
+        let supported = TunFeatures::TUN | TunFeatures::TAP | TunFeatures::NAPI;
+        let wanted = TunFeatures::TAP | TunFeatures::NAPI_FRAGS | TunFeatures::VNET_HDR;
+
+        let Err(NetworkError::Unsupported(message)) = supported.require(wanted) else {
+            panic!("missing features were accepted");
+        };
+        assert!(message.contains("NAPI_FRAGS") && message.contains("VNET_HDR"));
+        assert!(
+            !message.contains("TAP"),
+            "only missing features are listed: {message}"
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use std::net::Ipv4Addr;
+
+use super::{consts, error::NetworkError, settings::prefix_to_mask};
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_LD_H_ABS: u16 = 0x28;
+const BPF_LD_B_ABS: u16 = 0x30;
+const BPF_LD_H_IND: u16 = 0x48;
+const BPF_LDX_B_MSH: u16 = 0xb1;
+const BPF_ALU_AND_K: u16 = 0x54;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_JMP_JSET_K: u16 = 0x45;
+const BPF_RET_K: u16 = 0x06;
+
+/// Most instructions the kernel accepts in a classic BPF program.
+pub const BPF_MAXINSNS: usize = 4096;
+
+const ACCEPT: u32 = u32::MAX;
+const DROP: u32 = 0;
+
+/// Length of the Ethernet header in front of the Ip header.
+const ETH_HLEN: u32 = 14;
+const ETH_TYPE_OFFSET: u32 = 12;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/// A single classic BPF instruction, laid out like the kernel's `struct sock_filter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BpfInstruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// A classic BPF program deciding which frames the host may send into a tap device.
+///
+/// Programs are run against each frame before it is queued for us. Returning 0 drops it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BpfProgram {
+    instructions: Vec<BpfInstruction>,
+}
+
+impl BpfProgram {
+    pub fn new(instructions: Vec<BpfInstruction>) -> Result<Self, NetworkError> {
+        if instructions.is_empty() || instructions.len() > BPF_MAXINSNS {
+            return Err(NetworkError::Unsupported(format!(
+                "BPF programs must have between 1 and {BPF_MAXINSNS} instructions (got '{}')",
+                instructions.len()
+            )));
+        }
+        Ok(Self { instructions })
+    }
+
+    pub fn instructions(&self) -> &[BpfInstruction] {
+        &self.instructions
+    }
+}
+
+/// Packets a [`PacketFilter`] lets through.
+///
+/// Rules match Ethernet frames without a VLAN tag, as read from a tap device. Ipv6 rules don't
+/// follow extension headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterRule {
+    Ipv4,
+    Ipv6,
+    /// Packets carrying the given Ip protocol number, such as 1 for ICMP.
+    Protocol(u8),
+    /// UDP packets with the given source or destination port.
+    UdpPort(u16),
+    /// TCP packets with the given source or destination port.
+    TcpPort(u16),
+    /// Ipv4 packets from the given network.
+    Ipv4Source(Ipv4Addr, u8),
+    /// Ipv4 packets to the given network.
+    Ipv4Destination(Ipv4Addr, u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Jump {
+    Next,
+    Fail,
+    To(usize),
+}
+
+struct Op {
+    code: u16,
+    jt: Jump,
+    jf: Jump,
+    k: u32,
+}
+
+fn op(code: u16, k: u32) -> Op {
+    Op {
+        code,
+        jt: Jump::Next,
+        jf: Jump::Next,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: Jump, jf: Jump) -> Op {
+    Op { code, jt, jf, k }
+}
+
+impl FilterRule {
+    /// Instructions which return `ACCEPT` on a match and otherwise fall through to the next rule.
+    fn compile(&self) -> Vec<Op> {
+        let ipv4 = consts::ETH_P_IP as u32;
+        let ipv6 = consts::ETH_P_IPV6 as u32;
+        let ethertype = op(BPF_LD_H_ABS, ETH_TYPE_OFFSET);
+
+        let mut ops = vec![];
+        match *self {
+            FilterRule::Ipv4 | FilterRule::Ipv6 => {
+                let protocol = if *self == FilterRule::Ipv4 {
+                    ipv4
+                } else {
+                    ipv6
+                };
+                ops.push(ethertype);
+                ops.push(jump(BPF_JMP_JEQ_K, protocol, Jump::Next, Jump::Fail));
+            }
+            FilterRule::Protocol(protocol) => {
+                ops.extend([
+                    ethertype,
+                    jump(BPF_JMP_JEQ_K, ipv4, Jump::Next, Jump::To(4)),
+                    op(BPF_LD_B_ABS, ETH_HLEN + 9),
+                    jump(BPF_JMP_JEQ_K, protocol as u32, Jump::To(7), Jump::Fail),
+                    jump(BPF_JMP_JEQ_K, ipv6, Jump::Next, Jump::Fail),
+                    op(BPF_LD_B_ABS, ETH_HLEN + 6),
+                    jump(BPF_JMP_JEQ_K, protocol as u32, Jump::Next, Jump::Fail),
+                ]);
+            }
+            FilterRule::UdpPort(port) | FilterRule::TcpPort(port) => {
+                let protocol = match self {
+                    FilterRule::UdpPort(_) => IPPROTO_UDP,
+                    _ => IPPROTO_TCP,
+                } as u32;
+                let port = port as u32;
+
+                ops.extend([
+                    ethertype,
+                    jump(BPF_JMP_JEQ_K, ipv4, Jump::Next, Jump::To(11)),
+                    op(BPF_LD_B_ABS, ETH_HLEN + 9),
+                    jump(BPF_JMP_JEQ_K, protocol, Jump::Next, Jump::Fail),
+                    // Only the first fragment carries the ports
+                    op(BPF_LD_H_ABS, ETH_HLEN + 6),
+                    jump(BPF_JMP_JSET_K, 0x1FFF, Jump::Fail, Jump::Next),
+                    op(BPF_LDX_B_MSH, ETH_HLEN),
+                    op(BPF_LD_H_IND, ETH_HLEN),
+                    jump(BPF_JMP_JEQ_K, port, Jump::To(18), Jump::Next),
+                    op(BPF_LD_H_IND, ETH_HLEN + 2),
+                    jump(BPF_JMP_JEQ_K, port, Jump::To(18), Jump::Fail),
+                    jump(BPF_JMP_JEQ_K, ipv6, Jump::Next, Jump::Fail),
+                    op(BPF_LD_B_ABS, ETH_HLEN + 6),
+                    jump(BPF_JMP_JEQ_K, protocol, Jump::Next, Jump::Fail),
+                    op(BPF_LD_H_ABS, ETH_HLEN + 40),
+                    jump(BPF_JMP_JEQ_K, port, Jump::To(18), Jump::Next),
+                    op(BPF_LD_H_ABS, ETH_HLEN + 42),
+                    jump(BPF_JMP_JEQ_K, port, Jump::Next, Jump::Fail),
+                ]);
+            }
+            FilterRule::Ipv4Source(address, prefix_len)
+            | FilterRule::Ipv4Destination(address, prefix_len) => {
+                let offset = match self {
+                    FilterRule::Ipv4Source(..) => 12,
+                    _ => 16,
+                };
+                let mask = prefix_to_mask(prefix_len);
+
+                ops.extend([
+                    ethertype,
+                    jump(BPF_JMP_JEQ_K, ipv4, Jump::Next, Jump::Fail),
+                    op(BPF_LD_W_ABS, ETH_HLEN + offset),
+                    op(BPF_ALU_AND_K, mask),
+                    jump(
+                        BPF_JMP_JEQ_K,
+                        u32::from(address) & mask,
+                        Jump::Next,
+                        Jump::Fail,
+                    ),
+                ]);
+            }
+        }
+
+        ops.push(op(BPF_RET_K, ACCEPT));
+        ops
+    }
+}
+
+/// Builds a [`BpfProgram`] letting through packets which match any of its rules.
+///
+/// ```ignore
+/// let program = PacketFilter::new()
+///     .allow(FilterRule::UdpPort(5000))
+///     .build()?;
+/// tap.attach_filter(&program)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketFilter {
+    rules: Vec<FilterRule>,
+}
+
+impl PacketFilter {
+    /// An empty filter, which drops everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow(mut self, rule: FilterRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn build(&self) -> Result<BpfProgram, NetworkError> {
+        for rule in &self.rules {
+            if let FilterRule::Ipv4Source(_, prefix_len)
+            | FilterRule::Ipv4Destination(_, prefix_len) = rule
+            {
+                if *prefix_len > 32 {
+                    return Err(NetworkError::InvalidSubnetMask(format!(
+                        "Subnet mask value is invalid (got '{prefix_len}')"
+                    )));
+                }
+            }
+        }
+
+        let mut instructions = vec![];
+        for rule in &self.rules {
+            let ops = rule.compile();
+            let start = instructions.len();
+            let fail = start + ops.len();
+
+            for (i, op) in ops.iter().enumerate() {
+                let at = start + i;
+                let offset = |jump: Jump| {
+                    let target = match jump {
+                        Jump::Next => at + 1,
+                        Jump::Fail => fail,
+                        Jump::To(i) => start + i,
+                    };
+                    u8::try_from(target - at - 1).map_err(|_| {
+                        NetworkError::Unsupported(format!(
+                            "BPF jump is too far (from: {at}, to: {target})"
+                        ))
+                    })
+                };
+
+                instructions.push(BpfInstruction {
+                    code: op.code,
+                    jt: offset(op.jt)?,
+                    jf: offset(op.jf)?,
+                    k: op.k,
+                });
+            }
+        }
+        instructions.push(BpfInstruction {
+            code: BPF_RET_K,
+            jt: 0,
+            jf: 0,
+            k: DROP,
+        });
+
+        BpfProgram::new(instructions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `program` over `frame` the way the kernel does, returning what it returns.
+    fn run(program: &BpfProgram, frame: &[u8]) -> u32 {
+        let load = |offset: u32, len: usize| {
+            let offset = offset as usize;
+            frame
+                .get(offset..offset + len)
+                .map(|bytes| bytes.iter().fold(0, |value, &b| (value << 8) | b as u32))
+        };
+
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0usize);
+        loop {
+            let BpfInstruction { code, jt, jf, k } = program.instructions()[pc];
+            pc += 1;
+            // Loads past the end of the frame make the kernel drop it
+            let loaded = match code {
+                BPF_LD_W_ABS => Some(load(k, 4)),
+                BPF_LD_H_ABS => Some(load(k, 2)),
+                BPF_LD_B_ABS => Some(load(k, 1)),
+                BPF_LD_H_IND => Some(load(x + k, 2)),
+                _ => None,
+            };
+            match (code, loaded) {
+                (_, Some(Some(value))) => a = value,
+                (_, Some(None)) => return DROP,
+                (BPF_LDX_B_MSH, _) => match load(k, 1) {
+                    Some(value) => x = (value & 0xF) * 4,
+                    None => return DROP,
+                },
+                (BPF_ALU_AND_K, _) => a &= k,
+                (BPF_JMP_JEQ_K, _) => pc += if a == k { jt } else { jf } as usize,
+                (BPF_JMP_JSET_K, _) => pc += if a & k != 0 { jt } else { jf } as usize,
+                (BPF_RET_K, _) => return k,
+                _ => panic!("unexpected BPF instruction {code:#x}"),
+            }
+        }
+    }
+
+    fn ipv4_frame(protocol: u8, source: Ipv4Addr, ports: (u16, u16), fragment: u16) -> Vec<u8> {
+        let mut frame = vec![0; 14];
+        frame[12..14].copy_from_slice(&consts::ETH_P_IP.to_be_bytes());
+        // Options make the header 24 bytes, so the ports are only found through the IHL
+        let mut header = vec![0x46, 0, 0, 52, 0, 0];
+        header.extend(fragment.to_be_bytes());
+        header.extend([64, protocol, 0, 0]);
+        header.extend(source.octets());
+        header.extend([10, 0, 0, 1]);
+        header.extend([0; 4]);
+        frame.extend(header);
+        frame.extend(ports.0.to_be_bytes());
+        frame.extend(ports.1.to_be_bytes());
+        frame.extend([0; 20]);
+        frame
+    }
+
+    fn ipv6_frame(protocol: u8, ports: (u16, u16)) -> Vec<u8> {
+        let mut frame = vec![0; 14];
+        frame[12..14].copy_from_slice(&consts::ETH_P_IPV6.to_be_bytes());
+        frame.extend([0x60, 0, 0, 0, 0, 28, protocol, 64]);
+        frame.extend([0; 32]);
+        frame.extend(ports.0.to_be_bytes());
+        frame.extend(ports.1.to_be_bytes());
+        frame.extend([0; 24]);
+        frame
+    }
+
+    fn accepts(filter: &PacketFilter, frame: &[u8]) -> bool {
+        run(&filter.build().unwrap(), frame) == ACCEPT
+    }
+
+    #[test]
+    fn empty_filter_drops_everything() {
+        let program = PacketFilter::new().build().unwrap();
+        assert_eq!(
+            program.instructions(),
+            [BpfInstruction {
+                code: BPF_RET_K,
+                jt: 0,
+                jf: 0,
+                k: DROP
+            }]
+        );
+    }
+
+    #[test]
+    fn ethertype_rule_compiles_to_a_single_comparison() {
+        let program = PacketFilter::new().allow(FilterRule::Ipv6).build().unwrap();
+        let instructions = program.instructions();
+        assert_eq!(instructions.len(), 4);
+        assert_eq!(
+            instructions[1],
+            BpfInstruction {
+                code: BPF_JMP_JEQ_K,
+                jt: 0,
+                jf: 1,
+                k: consts::ETH_P_IPV6 as u32,
+            }
+        );
+        assert_eq!(instructions[2].k, ACCEPT);
+        assert_eq!(instructions[3].k, DROP);
+    }
+
+    #[test]
+    fn jumps_stay_inside_the_program() {
+        let filter = PacketFilter::new()
+            .allow(FilterRule::Ipv4)
+            .allow(FilterRule::Protocol(1))
+            .allow(FilterRule::UdpPort(53))
+            .allow(FilterRule::TcpPort(80))
+            .allow(FilterRule::Ipv4Source(Ipv4Addr::new(192, 168, 0, 0), 16));
+        let program = filter.build().unwrap();
+        let len = program.instructions().len();
+        for (i, instruction) in program.instructions().iter().enumerate() {
+            if instruction.code == BPF_RET_K {
+                continue;
+            }
+            assert!(i + 1 + (instruction.jt as usize) < len);
+            assert!(i + 1 + (instruction.jf as usize) < len);
+        }
+        assert_eq!(program.instructions()[len - 1].code, BPF_RET_K);
+    }
+
+    #[test]
+    fn protocol_rule_matches_both_ip_versions() {
+        let filter = PacketFilter::new().allow(FilterRule::Protocol(IPPROTO_UDP));
+        let source = Ipv4Addr::new(10, 0, 0, 2);
+        assert!(accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_UDP, source, (1, 2), 0)
+        ));
+        assert!(accepts(&filter, &ipv6_frame(IPPROTO_UDP, (1, 2))));
+        assert!(!accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_TCP, source, (1, 2), 0)
+        ));
+        assert!(!accepts(&filter, &ipv6_frame(IPPROTO_TCP, (1, 2))));
+    }
+
+    #[test]
+    fn port_rules_match_either_port_of_the_right_protocol() {
+        let filter = PacketFilter::new().allow(FilterRule::UdpPort(53));
+        let source = Ipv4Addr::new(10, 0, 0, 2);
+        assert!(accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_UDP, source, (53, 4000), 0)
+        ));
+        assert!(accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_UDP, source, (4000, 53), 0)
+        ));
+        assert!(accepts(&filter, &ipv6_frame(IPPROTO_UDP, (4000, 53))));
+        assert!(!accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_TCP, source, (53, 4000), 0)
+        ));
+        assert!(!accepts(&filter, &ipv6_frame(IPPROTO_UDP, (4000, 4001))));
+        // Later fragments have no ports to match
+        assert!(!accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_UDP, source, (53, 53), 8)
+        ));
+    }
+
+    #[test]
+    fn rules_are_tried_in_turn() {
+        let filter = PacketFilter::new()
+            .allow(FilterRule::TcpPort(80))
+            .allow(FilterRule::Ipv4Source(Ipv4Addr::new(192, 168, 0, 0), 16));
+        let inside = Ipv4Addr::new(192, 168, 3, 4);
+        let outside = Ipv4Addr::new(192, 169, 3, 4);
+        assert!(accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_TCP, outside, (80, 9000), 0)
+        ));
+        assert!(accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_UDP, inside, (1, 2), 0)
+        ));
+        assert!(!accepts(
+            &filter,
+            &ipv4_frame(IPPROTO_UDP, outside, (1, 2), 0)
+        ));
+        assert!(!accepts(&filter, &ipv6_frame(IPPROTO_UDP, (80, 2))));
+    }
+
+    #[test]
+    fn programs_over_the_kernel_limit_are_rejected() {
+        let instruction = BpfInstruction {
+            code: BPF_RET_K,
+            jt: 0,
+            jf: 0,
+            k: ACCEPT,
+        };
+        assert!(BpfProgram::new(vec![instruction; BPF_MAXINSNS]).is_ok());
+        assert!(BpfProgram::new(vec![instruction; BPF_MAXINSNS + 1]).is_err());
+        assert!(BpfProgram::new(vec![]).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use bitflags::bitflags;
+
+use super::consts;
+
+bitflags! {
+    /// Interface flags as reported by `SIOCGIFFLAGS`.
+    ///
+    /// `LOWER_UP`, `DORMANT` and `ECHO` do not fit in the 16 bit `ifr_flags` field and are only
+    /// populated when read through [`NetworkTun::flags`](super::NetworkTun::flags).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct InterfaceFlags: u32 {
+        const UP = consts::IFF_UP as u32;
+        const BROADCAST = consts::IFF_BROADCAST as u32;
+        const DEBUG = consts::IFF_DEBUG as u32;
+        const LOOPBACK = consts::IFF_LOOPBACK as u32;
+        const POINTOPOINT = consts::IFF_POINTOPOINT as u32;
+        const NOTRAILERS = consts::IFF_NOTRAILERS as u32;
+        const RUNNING = consts::IFF_RUNNING as u32;
+        const NOARP = consts::IFF_NOARP as u32;
+        const PROMISC = consts::IFF_PROMISC as u32;
+        const ALLMULTI = consts::IFF_ALLMULTI as u32;
+        const MASTER = consts::IFF_MASTER as u32;
+        const SLAVE = consts::IFF_SLAVE as u32;
+        const MULTICAST = consts::IFF_MULTICAST as u32;
+        const PORTSEL = consts::IFF_PORTSEL as u32;
+        const AUTOMEDIA = consts::IFF_AUTOMEDIA as u32;
+        const DYNAMIC = consts::IFF_DYNAMIC as u32;
+        const LOWER_UP = consts::IFF_LOWER_UP;
+        const DORMANT = consts::IFF_DORMANT;
+        const ECHO = consts::IFF_ECHO;
+    }
+}
+
+impl InterfaceFlags {
+    /// Flags which are maintained by the kernel and cannot be changed with `SIOCSIFFLAGS`.
+    pub const READ_ONLY: Self = Self::RUNNING
+        .union(Self::LOWER_UP)
+        .union(Self::DORMANT)
+        .union(Self::ECHO);
+
+    pub(crate) fn from_ifr_flags(flags: u16) -> Self {
+        Self::from_bits_retain(flags as u32)
+    }
+
+    pub(crate) fn to_ifr_flags(self) -> u16 {
+        self.bits() as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ifr_flags_round_trip() {
+        let flags = InterfaceFlags::UP
+            | InterfaceFlags::POINTOPOINT
+            | InterfaceFlags::NOARP
+            | InterfaceFlags::MULTICAST
+            | InterfaceFlags::DYNAMIC;
+        assert_eq!(InterfaceFlags::from_ifr_flags(flags.to_ifr_flags()), flags);
+
+        for raw in [0, 1, 0x1003, 0x8000, u16::MAX] {
+            assert_eq!(InterfaceFlags::from_ifr_flags(raw).to_ifr_flags(), raw);
+        }
+    }
+
+    #[test]
+    fn flags_beyond_ifr_flags_are_dropped() {
+        let flags = InterfaceFlags::UP | InterfaceFlags::LOWER_UP | InterfaceFlags::DORMANT;
+        assert_eq!(flags.to_ifr_flags(), consts::IFF_UP);
+        assert!(InterfaceFlags::READ_ONLY.contains(InterfaceFlags::LOWER_UP));
+        assert!(!InterfaceFlags::READ_ONLY.contains(InterfaceFlags::UP));
+    }
+}
@@ -0,0 +1,162 @@
+use std::{net::IpAddr, os::fd::OwnedFd};
+
+use nix::{
+    errno::Errno,
+    sys::socket::{socket, AddressFamily, SockFlag, SockType},
+};
+
+use super::{
+    backend::{IoctlBackend, NetworkBackend as _},
+    error::NetworkError,
+    flags::InterfaceFlags,
+    settings::IfReq,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub index: u32,
+    pub flags: InterfaceFlags,
+    pub mtu: u32,
+    /// Assigned addresses along with their prefix lengths.
+    pub addresses: Vec<(IpAddr, u8)>,
+    /// Link-layer address, empty for devices without one such as tun devices.
+    pub hardware_address: Vec<u8>,
+    /// The driver behind the interface as reported by ethtool, such as `tun`. `None` for
+    /// drivers without ethtool support.
+    pub driver: Option<String>,
+}
+
+impl InterfaceInfo {
+    /// Whether the interface is a tun or tap device, regardless of which process created it.
+    pub fn is_tun_tap(&self) -> bool {
+        self.driver.as_deref() == Some("tun")
+    }
+}
+
+impl std::fmt::Display for InterfaceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} mtu {} flags {:?}",
+            self.index, self.name, self.mtu, self.flags
+        )?;
+        if !self.hardware_address.is_empty() {
+            let hardware_address = self
+                .hardware_address
+                .iter()
+                .map(|x| format!("{x:02x}"))
+                .collect::<Vec<_>>();
+            write!(f, " link {}", hardware_address.join(":"))?;
+        }
+        for (address, prefix_len) in &self.addresses {
+            write!(f, " {address}/{prefix_len}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists every interface in the current network namespace, ordered by index.
+pub fn list_interfaces() -> Result<Vec<InterfaceInfo>, NetworkError> {
+    let ifaddrs = nix::ifaddrs::getifaddrs().map_err(NetworkError::call("getifaddrs"))?;
+    let backend = IoctlBackend::new()?;
+    let sock_fd = socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::empty(),
+        None,
+    )
+    .map_err(NetworkError::call("ioctl socket"))?;
+
+    let mut interfaces: Vec<InterfaceInfo> = vec![];
+    let mut vanished: Vec<String> = vec![];
+    for ifaddr in ifaddrs {
+        if vanished.contains(&ifaddr.interface_name) {
+            continue;
+        }
+
+        let position = match interfaces
+            .iter()
+            .position(|interface| interface.name == ifaddr.interface_name)
+        {
+            Some(position) => position,
+            None => {
+                let name = ifaddr.interface_name.as_str();
+                let flags = InterfaceFlags::from_bits_retain(ifaddr.flags.bits() as u32);
+                match describe(name, flags, &backend, &sock_fd) {
+                    Ok(interface) => interfaces.push(interface),
+                    // Deleted since getifaddrs, which is likely while cleaning up interfaces
+                    Err(e) if matches!(e.errno(), Some(Errno::ENODEV | Errno::ENXIO)) => {
+                        vanished.push(name.to_owned());
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                interfaces.len() - 1
+            }
+        };
+        let interface = &mut interfaces[position];
+
+        let Some(address) = ifaddr.address else {
+            continue;
+        };
+
+        if let Some(link) = address.as_link_addr() {
+            if let Some(hardware_address) = link.addr() {
+                interface.hardware_address = hardware_address[..link.halen().min(6)].to_vec();
+            }
+        } else if let Some(v4) = address.as_sockaddr_in() {
+            let prefix_len = ifaddr
+                .netmask
+                .and_then(|mask| {
+                    mask.as_sockaddr_in()
+                        .map(|mask| u32::from(mask.ip()).count_ones())
+                })
+                .unwrap_or(32);
+            interface
+                .addresses
+                .push((IpAddr::V4(v4.ip()), prefix_len as u8));
+        } else if let Some(v6) = address.as_sockaddr_in6() {
+            let prefix_len = ifaddr
+                .netmask
+                .and_then(|mask| {
+                    mask.as_sockaddr_in6()
+                        .map(|mask| u128::from(mask.ip()).count_ones())
+                })
+                .unwrap_or(128);
+            interface
+                .addresses
+                .push((IpAddr::V6(v6.ip()), prefix_len as u8));
+        }
+    }
+
+    interfaces.sort_by_key(|interface| interface.index);
+    Ok(interfaces)
+}
+
+/// Everything about an interface except its addresses, which `getifaddrs` lists separately.
+fn describe(
+    name: &str,
+    flags: InterfaceFlags,
+    backend: &IoctlBackend,
+    sock_fd: &OwnedFd,
+) -> Result<InterfaceInfo, NetworkError> {
+    Ok(InterfaceInfo {
+        name: name.to_owned(),
+        index: nix::net::if_::if_nametoindex(name)
+            .map_err(NetworkError::on("if_nametoindex", name))?,
+        flags,
+        mtu: backend.mtu(name)?,
+        addresses: vec![],
+        hardware_address: vec![],
+        // Asked now, as the socket is in the namespace being listed
+        driver: IfReq::new(name)?.ethtool_driver(sock_fd).ok(),
+    })
+}
+
+/// Looks up a single interface by name.
+pub fn find_interface(name: &str) -> Result<Option<InterfaceInfo>, NetworkError> {
+    Ok(list_interfaces()?
+        .into_iter()
+        .find(|interface| interface.name == name))
+}
@@ -0,0 +1,129 @@
+use super::error::NetworkError;
+
+/// An Ethernet hardware address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddr([u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: Self = Self([0xFF; 6]);
+
+    pub const fn new(octets: [u8; 6]) -> Self {
+        Self(octets)
+    }
+
+    /// A locally administered unicast address derived from a radio node ID, so a node keeps the
+    /// same address across restarts. Different node IDs always give different addresses.
+    pub const fn from_node_id(node_id: u32) -> Self {
+        let id = node_id.to_be_bytes();
+        Self([0x02, 0x00, id[0], id[1], id[2], id[3]])
+    }
+
+    pub const fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    pub const fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    /// Whether the address was assigned locally rather than by the manufacturer.
+    pub const fn is_local(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> Self {
+        Self(octets)
+    }
+}
+
+impl From<MacAddr> for [u8; 6] {
+    fn from(address: MacAddr) -> Self {
+        address.0
+    }
+}
+
+impl std::fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl std::str::FromStr for MacAddr {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            NetworkError::InvalidMacAddress(format!(
+                "MAC addresses must be six hex octets separated by ':' (got '{s}')"
+            ))
+        };
+
+        let mut octets = [0u8; 6];
+        let mut parts = s.split(':');
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or_else(invalid)?;
+            // from_str_radix alone would also take a sign, as in "+1"
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self(octets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_displays() {
+        let address = "02:00:0a:Ff:00:7B".parse::<MacAddr>().unwrap();
+        assert_eq!(address.octets(), [0x02, 0x00, 0x0A, 0xFF, 0x00, 0x7B]);
+        assert_eq!(address.to_string(), "02:00:0a:ff:00:7b");
+        assert_eq!(address.to_string().parse::<MacAddr>().unwrap(), address);
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for invalid in [
+            "",
+            "02:00:0a:ff:00",
+            "02:00:0a:ff:00:7b:01",
+            "02:00:0a:ff:00:7",
+            "02:00:0a:ff:00:007",
+            "02-00-0a-ff-00-7b",
+            "02:00:0a:ff:00:zz",
+            "02:00:0a:ff:00:+1",
+            "02:00:0a:ff:00:7b:",
+        ] {
+            assert!(
+                matches!(
+                    invalid.parse::<MacAddr>(),
+                    Err(NetworkError::InvalidMacAddress(_))
+                ),
+                "accepted '{invalid}'"
+            );
+        }
+    }
+
+    #[test]
+    fn node_ids_give_local_unicast_addresses() {
+        let address = MacAddr::from_node_id(0x01020304);
+        assert_eq!(address.octets(), [0x02, 0x00, 1, 2, 3, 4]);
+        assert!(address.is_local());
+        assert!(!address.is_multicast());
+        assert!(MacAddr::BROADCAST.is_multicast() && MacAddr::BROADCAST.is_broadcast());
+    }
+}
@@ -1,7 +1,29 @@
+pub mod backend;
+pub mod buf;
 pub mod consts;
 pub mod error;
+pub mod features;
+pub mod filter;
+pub mod flags;
+pub mod interfaces;
+pub mod mac;
+#[cfg(feature = "netlink")]
+pub mod monitor;
+pub mod multicast;
+#[cfg(feature = "netlink")]
+pub mod netlink;
+pub mod netns;
 pub mod packet;
+pub mod pool;
+pub mod preflight;
+pub mod routes;
 pub mod settings;
+pub mod split;
+pub mod stats;
+mod teardown;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+pub mod vnet;
 
 mod tun;
 pub use tun::NetworkTun;
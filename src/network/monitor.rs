@@ -0,0 +1,394 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+};
+
+use nix::libc;
+
+use super::{
+    error::NetworkError,
+    flags::InterfaceFlags,
+    netlink::{
+        attr_str, attributes, dump_links, index_of, parse_address, InterfaceInfoMessage,
+        NetlinkResponse, NetlinkSocket,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkEvent {
+    Up {
+        index: u32,
+        name: String,
+        flags: InterfaceFlags,
+    },
+    Down {
+        index: u32,
+        name: String,
+        flags: InterfaceFlags,
+    },
+    /// Carrier (`LOWER_UP`) was gained or lost, such as through
+    /// [`NetworkTun::set_carrier`](super::NetworkTun::set_carrier). Taking an interface down
+    /// also loses carrier.
+    Carrier {
+        index: u32,
+        name: String,
+        carrier: bool,
+    },
+    AddressAdded {
+        index: u32,
+        name: String,
+        address: IpAddr,
+        prefix_len: u8,
+    },
+    AddressRemoved {
+        index: u32,
+        name: String,
+        address: IpAddr,
+        prefix_len: u8,
+    },
+    Renamed {
+        index: u32,
+        old_name: String,
+        new_name: String,
+    },
+    Deleted {
+        index: u32,
+        name: String,
+    },
+}
+
+impl LinkEvent {
+    pub fn index(&self) -> u32 {
+        match self {
+            LinkEvent::Up { index, .. }
+            | LinkEvent::Down { index, .. }
+            | LinkEvent::Carrier { index, .. }
+            | LinkEvent::AddressAdded { index, .. }
+            | LinkEvent::AddressRemoved { index, .. }
+            | LinkEvent::Renamed { index, .. }
+            | LinkEvent::Deleted { index, .. } => *index,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LinkState {
+    name: String,
+    flags: InterfaceFlags,
+}
+
+/// Watches rtnetlink for link and address changes and turns them into [`LinkEvent`]s.
+///
+/// By default every interface is reported; use [`LinkMonitor::watch`] to restrict the monitor to
+/// specific interfaces.
+pub struct LinkMonitor {
+    socket: NetlinkSocket,
+    links: HashMap<u32, LinkState>,
+    watched: HashSet<u32>,
+    pending: VecDeque<LinkEvent>,
+}
+
+impl LinkMonitor {
+    pub fn new() -> Result<Self, NetworkError> {
+        let groups = libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR;
+        let socket = NetlinkSocket::new(groups as u32)?;
+
+        // The dump goes through its own socket so it can't swallow events on the subscription
+        let links = dump_links(&mut NetlinkSocket::new(0)?)?
+            .into_iter()
+            .map(|(info, attrs)| (info.index as u32, link_state(&info, &attrs)))
+            .collect();
+
+        Ok(Self {
+            socket,
+            links,
+            watched: HashSet::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Only report events for the named interface (and any others already watched).
+    ///
+    /// Interfaces are tracked by index, so events keep being reported after a rename.
+    pub fn watch(&mut self, name: &str) -> Result<&mut Self, NetworkError> {
+        self.watched.insert(index_of(name)?);
+        Ok(self)
+    }
+
+    /// Blocks until the next event for a watched interface arrives.
+    pub fn next_event(&mut self) -> Result<LinkEvent, NetworkError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+
+            let events = match self.socket.recv_messages() {
+                Ok(responses) => responses
+                    .iter()
+                    .flat_map(|response| self.handle(response))
+                    .collect(),
+                // The kernel dropped events because we fell behind, so catch up from a dump
+                Err(e) if e.errno() == Some(nix::errno::Errno::ENOBUFS) => self.resync()?,
+                Err(e) => return Err(e),
+            };
+            self.pending.extend(
+                events.into_iter().filter(|event| {
+                    self.watched.is_empty() || self.watched.contains(&event.index())
+                }),
+            );
+        }
+    }
+
+    /// Compares a fresh dump with what we last saw, reporting the link changes in between.
+    ///
+    /// Address changes which were dropped can't be recovered this way.
+    fn resync(&mut self) -> Result<Vec<LinkEvent>, NetworkError> {
+        let links = dump_links(&mut NetlinkSocket::new(0)?)?
+            .into_iter()
+            .map(|(info, attrs)| (info.index as u32, link_state(&info, &attrs)))
+            .collect::<HashMap<_, _>>();
+
+        let mut events = vec![];
+        for (index, old) in std::mem::take(&mut self.links) {
+            match links.get(&index) {
+                Some(new) => events.extend(link_changes(index, old, new)),
+                None => events.push(LinkEvent::Deleted {
+                    index,
+                    name: old.name,
+                }),
+            }
+        }
+        self.links = links;
+
+        Ok(events)
+    }
+
+    fn name_of(&self, index: u32) -> String {
+        self.links
+            .get(&index)
+            .map(|link| link.name.clone())
+            .unwrap_or_default()
+    }
+
+    fn handle(&mut self, response: &NetlinkResponse) -> Vec<LinkEvent> {
+        match response.header.msg_type {
+            libc::RTM_NEWLINK => {
+                let Some((info, attrs)) = response.split::<InterfaceInfoMessage>() else {
+                    return vec![];
+                };
+                let index = info.index as u32;
+                let new = link_state(&info, attrs);
+
+                // Links we haven't seen before have nothing to compare against
+                match self.links.insert(index, new) {
+                    Some(old) => link_changes(index, old, &self.links[&index]),
+                    None => vec![],
+                }
+            }
+            libc::RTM_DELLINK => {
+                let Some((info, _)) = response.split::<InterfaceInfoMessage>() else {
+                    return vec![];
+                };
+                let index = info.index as u32;
+                let Some(link) = self.links.remove(&index) else {
+                    return vec![];
+                };
+
+                vec![LinkEvent::Deleted {
+                    index,
+                    name: link.name,
+                }]
+            }
+            libc::RTM_NEWADDR => {
+                let Some((ifaddr, address)) = parse_address(response) else {
+                    return vec![];
+                };
+
+                vec![LinkEvent::AddressAdded {
+                    index: ifaddr.index,
+                    name: self.name_of(ifaddr.index),
+                    address,
+                    prefix_len: ifaddr.prefix_len,
+                }]
+            }
+            libc::RTM_DELADDR => {
+                let Some((ifaddr, address)) = parse_address(response) else {
+                    return vec![];
+                };
+
+                vec![LinkEvent::AddressRemoved {
+                    index: ifaddr.index,
+                    name: self.name_of(ifaddr.index),
+                    address,
+                    prefix_len: ifaddr.prefix_len,
+                }]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// Every change between two states of a link, with a rename reported first, then the interface
+/// going up or down, then carrier.
+fn link_changes(index: u32, old: LinkState, new: &LinkState) -> Vec<LinkEvent> {
+    let mut events = vec![];
+    if old.name != new.name {
+        events.push(LinkEvent::Renamed {
+            index,
+            old_name: old.name,
+            new_name: new.name.clone(),
+        });
+    }
+
+    let was_up = old.flags.contains(InterfaceFlags::UP);
+    let is_up = new.flags.contains(InterfaceFlags::UP);
+    if !was_up && is_up {
+        events.push(LinkEvent::Up {
+            index,
+            name: new.name.clone(),
+            flags: new.flags,
+        });
+    } else if was_up && !is_up {
+        events.push(LinkEvent::Down {
+            index,
+            name: new.name.clone(),
+            flags: new.flags,
+        });
+    }
+
+    let had_carrier = old.flags.contains(InterfaceFlags::LOWER_UP);
+    let has_carrier = new.flags.contains(InterfaceFlags::LOWER_UP);
+    if had_carrier != has_carrier {
+        events.push(LinkEvent::Carrier {
+            index,
+            name: new.name.clone(),
+            carrier: has_carrier,
+        });
+    }
+
+    events
+}
+
+impl Iterator for LinkMonitor {
+    type Item = Result<LinkEvent, NetworkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+impl AsFd for LinkMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.fd().as_fd()
+    }
+}
+
+impl AsRawFd for LinkMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.fd().as_raw_fd()
+    }
+}
+
+fn link_state(info: &InterfaceInfoMessage, attrs: &[u8]) -> LinkState {
+    let name = attributes(attrs)
+        .find(|(kind, _)| *kind == libc::IFLA_IFNAME)
+        .map(|(_, data)| attr_str(data))
+        .unwrap_or_default();
+
+    LinkState {
+        name,
+        flags: InterfaceFlags::from_bits_retain(info.flags),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(name: &str, flags: InterfaceFlags) -> LinkState {
+        LinkState {
+            name: name.to_string(),
+            flags,
+        }
+    }
+
+    #[test]
+    fn unchanged_links_have_no_events() {
+        let flags = InterfaceFlags::UP | InterfaceFlags::LOWER_UP;
+        assert!(link_changes(3, state("tun0", flags), &state("tun0", flags)).is_empty());
+
+        // Flags other than UP and LOWER_UP aren't reported
+        let new = state("tun0", flags | InterfaceFlags::MULTICAST);
+        assert!(link_changes(3, state("tun0", flags), &new).is_empty());
+    }
+
+    #[test]
+    fn carrier_changes_are_reported_on_their_own() {
+        let up = InterfaceFlags::UP | InterfaceFlags::RUNNING;
+        let new = state("tun0", up | InterfaceFlags::LOWER_UP);
+        assert_eq!(
+            link_changes(3, state("tun0", InterfaceFlags::UP), &new),
+            [LinkEvent::Carrier {
+                index: 3,
+                name: "tun0".to_string(),
+                carrier: true,
+            }]
+        );
+
+        let old = state("tun0", up | InterfaceFlags::LOWER_UP);
+        assert_eq!(
+            link_changes(3, old, &state("tun0", InterfaceFlags::UP)),
+            [LinkEvent::Carrier {
+                index: 3,
+                name: "tun0".to_string(),
+                carrier: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn going_down_also_loses_carrier() {
+        let old = state("tun0", InterfaceFlags::UP | InterfaceFlags::LOWER_UP);
+        assert_eq!(
+            link_changes(3, old, &state("tun0", InterfaceFlags::empty())),
+            [
+                LinkEvent::Down {
+                    index: 3,
+                    name: "tun0".to_string(),
+                    flags: InterfaceFlags::empty(),
+                },
+                LinkEvent::Carrier {
+                    index: 3,
+                    name: "tun0".to_string(),
+                    carrier: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_come_first_and_use_the_new_name() {
+        let new = state("radio0", InterfaceFlags::UP | InterfaceFlags::LOWER_UP);
+        assert_eq!(
+            link_changes(3, state("tun0", InterfaceFlags::empty()), &new),
+            [
+                LinkEvent::Renamed {
+                    index: 3,
+                    old_name: "tun0".to_string(),
+                    new_name: "radio0".to_string(),
+                },
+                LinkEvent::Up {
+                    index: 3,
+                    name: "radio0".to_string(),
+                    flags: new.flags,
+                },
+                LinkEvent::Carrier {
+                    index: 3,
+                    name: "radio0".to_string(),
+                    carrier: true,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::{AsRawFd as _, OwnedFd},
+};
+
+use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+
+use super::{consts, error::NetworkError, mac::MacAddr, settings::IfReq};
+
+fn multicast_ioctl(
+    name: &str,
+    address: MacAddr,
+    (request, operation): (u64, &str),
+) -> Result<(), NetworkError> {
+    if !address.is_multicast() {
+        return Err(NetworkError::InvalidMacAddress(format!(
+            "Link-layer multicast entries need a multicast address (got '{address}')"
+        )));
+    }
+    let mut ifreq = IfReq::new(name)?;
+    ifreq.set_hardware_address(nix::libc::AF_UNSPEC as u16, address);
+
+    let sock_fd = socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::empty(),
+        None,
+    )
+    .map_err(NetworkError::call("ioctl socket"))?;
+
+    ifreq.ioctl(&sock_fd, (request, operation))
+}
+
+/// Adds `address` to the interface's link-layer multicast filter, so frames sent to it are
+/// accepted. Adding an address that is already there does nothing, and one delete removes it.
+pub fn add_link_multicast(name: &str, address: MacAddr) -> Result<(), NetworkError> {
+    multicast_ioctl(name, address, (consts::SIOCADDMULTI, "SIOCADDMULTI"))
+}
+
+pub fn delete_link_multicast(name: &str, address: MacAddr) -> Result<(), NetworkError> {
+    multicast_ioctl(name, address, (consts::SIOCDELMULTI, "SIOCDELMULTI"))
+}
+
+/// Membership of an Ipv4 or Ipv6 multicast group on one interface, held until this is dropped.
+///
+/// Joining makes the kernel announce the group over IGMP or MLD and accept frames for it. The
+/// membership belongs to a socket kept open for that purpose, so packets for the group are still
+/// read from the tun rather than from here.
+#[derive(Debug)]
+pub struct MulticastGroup {
+    group: IpAddr,
+    _sock_fd: OwnedFd,
+}
+
+impl MulticastGroup {
+    /// Joins `group` on the interface called `name` in the calling thread's namespace.
+    pub fn join(group: IpAddr, name: &str) -> Result<Self, NetworkError> {
+        if !group.is_multicast() {
+            return Err(NetworkError::Unsupported(format!(
+                "Only multicast groups can be joined (got '{group}')"
+            )));
+        }
+        let ifindex = nix::net::if_::if_nametoindex(name)
+            .map_err(NetworkError::on("if_nametoindex", name))?;
+
+        let sock_fd = match group {
+            IpAddr::V4(group) => join_ipv4(group, ifindex),
+            IpAddr::V6(group) => join_ipv6(group, ifindex),
+        }
+        .map_err(|e| e.with_interface(name))?;
+
+        Ok(Self {
+            group,
+            _sock_fd: sock_fd,
+        })
+    }
+
+    pub fn group(&self) -> IpAddr {
+        self.group
+    }
+}
+
+fn set_membership<T>(
+    sock_fd: &OwnedFd,
+    level: i32,
+    (option, operation): (i32, &str),
+    value: &T,
+) -> Result<(), NetworkError> {
+    let code = unsafe {
+        nix::libc::setsockopt(
+            sock_fd.as_raw_fd(),
+            level,
+            option,
+            value as *const T as *const nix::libc::c_void,
+            std::mem::size_of::<T>() as nix::libc::socklen_t,
+        )
+    };
+    nix::errno::Errno::result(code).map_err(NetworkError::call(operation))?;
+    Ok(())
+}
+
+fn join_ipv4(group: Ipv4Addr, ifindex: u32) -> Result<OwnedFd, NetworkError> {
+    let sock_fd = socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(NetworkError::call("multicast socket"))?;
+
+    // ip_mreqn rather than ip_mreq, so the interface is picked by index instead of by address
+    let request = nix::libc::ip_mreqn {
+        imr_multiaddr: nix::libc::in_addr {
+            s_addr: u32::from_ne_bytes(group.octets()),
+        },
+        imr_address: nix::libc::in_addr { s_addr: 0 },
+        imr_ifindex: ifindex as nix::libc::c_int,
+    };
+    set_membership(
+        &sock_fd,
+        nix::libc::IPPROTO_IP,
+        (nix::libc::IP_ADD_MEMBERSHIP, "IP_ADD_MEMBERSHIP"),
+        &request,
+    )?;
+
+    Ok(sock_fd)
+}
+
+fn join_ipv6(group: Ipv6Addr, ifindex: u32) -> Result<OwnedFd, NetworkError> {
+    let sock_fd = socket(
+        AddressFamily::Inet6,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(NetworkError::call("multicast socket"))?;
+
+    let request = nix::libc::ipv6_mreq {
+        ipv6mr_multiaddr: nix::libc::in6_addr {
+            s6_addr: group.octets(),
+        },
+        ipv6mr_interface: ifindex,
+    };
+    set_membership(
+        &sock_fd,
+        nix::libc::IPPROTO_IPV6,
+        (nix::libc::IPV6_ADD_MEMBERSHIP, "IPV6_ADD_MEMBERSHIP"),
+        &request,
+    )?;
+
+    Ok(sock_fd)
+}
@@ -0,0 +1,845 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::{AsRawFd as _, OwnedFd},
+    sync::Mutex,
+};
+
+use nix::{
+    libc,
+    sys::socket::{
+        bind, recv, send, socket, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol,
+        SockType,
+    },
+};
+
+use super::{
+    backend::{validate_alias, validate_prefix_len, NetworkBackend},
+    error::NetworkError,
+    flags::InterfaceFlags,
+    mac::MacAddr,
+    netns::NetNamespace,
+    settings::validate_name,
+};
+
+const NLMSG_HDRLEN: usize = std::mem::size_of::<NetlinkHeader>();
+const RTA_HDRLEN: usize = 4;
+const NLA_F_NESTED: u16 = 0x8000;
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub(crate) struct NetlinkHeader {
+    pub length: u32,
+    pub msg_type: u16,
+    pub flags: u16,
+    pub sequence: u32,
+    pub pid: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub(crate) struct InterfaceInfoMessage {
+    pub family: u8,
+    _0: u8,
+    pub device_type: u16,
+    pub index: i32,
+    pub flags: u32,
+    pub change: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub(crate) struct InterfaceAddressMessage {
+    pub family: u8,
+    pub prefix_len: u8,
+    pub flags: u8,
+    pub scope: u8,
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub(crate) struct NeighborMessage {
+    pub family: u8,
+    _0: [u8; 3],
+    pub index: i32,
+    pub state: u16,
+    pub flags: u8,
+    pub kind: u8,
+}
+
+/// Plain old data headers which can be copied in and out of netlink messages.
+///
+/// # Safety
+/// Implementors must be `repr(C)` and valid for any bit pattern.
+pub(crate) unsafe trait NetlinkPayload: Copy + Default {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+
+    fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < std::mem::size_of::<Self>() {
+            return None;
+        }
+        let mut value = Self::default();
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                &mut value as *mut Self as *mut u8,
+                std::mem::size_of::<Self>(),
+            )
+        };
+        Some(value)
+    }
+}
+
+unsafe impl NetlinkPayload for NetlinkHeader {}
+unsafe impl NetlinkPayload for InterfaceInfoMessage {}
+unsafe impl NetlinkPayload for InterfaceAddressMessage {}
+unsafe impl NetlinkPayload for NeighborMessage {}
+
+/// A netlink request under construction: a header, a fixed payload, then attributes.
+pub(crate) struct NetlinkMessage {
+    buf: Vec<u8>,
+}
+
+impl NetlinkMessage {
+    pub fn new<T: NetlinkPayload>(msg_type: u16, flags: u16, payload: &T) -> Self {
+        let header = NetlinkHeader {
+            msg_type,
+            // Always ask for an ack so non-dump requests have a well defined end
+            flags: flags | (libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16,
+            ..Default::default()
+        };
+
+        let mut message = Self { buf: vec![] };
+        message.buf.extend_from_slice(header.as_bytes());
+        message.buf.extend_from_slice(payload.as_bytes());
+        message.pad();
+        message
+    }
+
+    pub fn msg_type(&self) -> u16 {
+        u16::from_ne_bytes([self.buf[4], self.buf[5]])
+    }
+
+    fn pad(&mut self) {
+        self.buf.resize(align(self.buf.len()), 0);
+    }
+
+    pub fn attr(&mut self, kind: u16, data: &[u8]) -> &mut Self {
+        let len = (RTA_HDRLEN + data.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&kind.to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        self.pad();
+        self
+    }
+
+    pub fn attr_str(&mut self, kind: u16, data: &str) -> &mut Self {
+        let mut bytes = data.as_bytes().to_vec();
+        bytes.push(0);
+        self.attr(kind, &bytes)
+    }
+
+    pub fn attr_nested<F>(&mut self, kind: u16, build: F) -> &mut Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        let start = self.buf.len();
+        self.attr(kind | NLA_F_NESTED, &[]);
+        build(self);
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        self
+    }
+
+    fn finish(&mut self, sequence: u32) -> &[u8] {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf[8..12].copy_from_slice(&sequence.to_ne_bytes());
+        &self.buf
+    }
+}
+
+/// Iterates over the `(type, payload)` netlink attributes in `data`.
+pub(crate) fn attributes(data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset + RTA_HDRLEN > data.len() {
+            return None;
+        }
+        let len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
+        let kind = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]) & !NLA_F_NESTED;
+        if len < RTA_HDRLEN || offset + len > data.len() {
+            return None;
+        }
+        let payload = &data[offset + RTA_HDRLEN..offset + len];
+        offset += align(len);
+        Some((kind, payload))
+    })
+}
+
+pub(crate) fn attr_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(0..4)?.try_into().ok()?))
+}
+
+pub(crate) fn attr_ip(family: u8, data: &[u8]) -> Option<IpAddr> {
+    match family as i32 {
+        libc::AF_INET => Some(IpAddr::V4(Ipv4Addr::from(
+            <[u8; 4]>::try_from(data.get(0..4)?).ok()?,
+        ))),
+        libc::AF_INET6 => Some(IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(data.get(0..16)?).ok()?,
+        ))),
+        _ => None,
+    }
+}
+
+pub(crate) fn attr_str(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&x| x != 0)
+        .map(|&x| x as char)
+        .collect()
+}
+
+/// Extracts the address from an `RTM_NEWADDR` or `RTM_DELADDR` message.
+pub(crate) fn parse_address(
+    response: &NetlinkResponse,
+) -> Option<(InterfaceAddressMessage, IpAddr)> {
+    let (ifaddr, attrs) = response.split::<InterfaceAddressMessage>()?;
+
+    let mut address = None;
+    for (kind, data) in attributes(attrs) {
+        // IFA_LOCAL is the local address on point-to-point links, where IFA_ADDRESS is the peer,
+        // so it takes priority when present
+        if kind == libc::IFA_LOCAL || (kind == libc::IFA_ADDRESS && address.is_none()) {
+            address = attr_ip(ifaddr.family, data);
+        }
+    }
+
+    address.map(|address| (ifaddr, address))
+}
+
+/// A received netlink message, with the header split off from the payload.
+pub(crate) struct NetlinkResponse {
+    pub header: NetlinkHeader,
+    pub payload: Vec<u8>,
+}
+
+impl NetlinkResponse {
+    /// Splits the payload into its fixed header and trailing attributes.
+    pub fn split<T: NetlinkPayload>(&self) -> Option<(T, &[u8])> {
+        let value = T::from_bytes(&self.payload)?;
+        let attrs = self
+            .payload
+            .get(align(std::mem::size_of::<T>())..)
+            .unwrap_or_default();
+        Some((value, attrs))
+    }
+}
+
+pub(crate) struct NetlinkSocket {
+    fd: OwnedFd,
+    sequence: u32,
+}
+
+impl NetlinkSocket {
+    /// Opens a `NETLINK_ROUTE` socket subscribed to the given multicast `groups`.
+    pub fn new(groups: u32) -> Result<Self, NetworkError> {
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkRoute,
+        )
+        .map_err(NetworkError::call("netlink socket"))?;
+
+        bind(fd.as_raw_fd(), &NetlinkAddr::new(0, groups))
+            .map_err(NetworkError::call("netlink bind"))?;
+
+        Ok(Self { fd, sequence: 0 })
+    }
+
+    pub fn fd(&self) -> &OwnedFd {
+        &self.fd
+    }
+
+    /// Receives one datagram and splits it into its messages.
+    pub fn recv_messages(&self) -> Result<Vec<NetlinkResponse>, NetworkError> {
+        let mut buf = vec![0u8; 32768];
+        let n = recv(self.fd.as_raw_fd(), &mut buf, MsgFlags::empty())
+            .map_err(NetworkError::call("netlink recv"))?;
+
+        let mut messages = vec![];
+        let mut offset = 0;
+        while offset + NLMSG_HDRLEN <= n {
+            let Some(header) = NetlinkHeader::from_bytes(&buf[offset..n]) else {
+                break;
+            };
+            let len = header.length as usize;
+            if len < NLMSG_HDRLEN || offset + len > n {
+                break;
+            }
+            messages.push(NetlinkResponse {
+                header,
+                payload: buf[offset + NLMSG_HDRLEN..offset + len].to_vec(),
+            });
+            offset += align(len);
+        }
+
+        Ok(messages)
+    }
+
+    /// Sends `message` and collects every response until the kernel acknowledges it, or until
+    /// `NLMSG_DONE` for dump requests.
+    pub fn request(
+        &mut self,
+        message: &mut NetlinkMessage,
+    ) -> Result<Vec<NetlinkResponse>, NetworkError> {
+        self.sequence = self.sequence.wrapping_add(1);
+        let sequence = self.sequence;
+        let operation = message_name(message.msg_type());
+
+        send(
+            self.fd.as_raw_fd(),
+            message.finish(sequence),
+            MsgFlags::empty(),
+        )
+        .map_err(NetworkError::call(&operation))?;
+
+        let mut responses = vec![];
+        loop {
+            for response in self.recv_messages()? {
+                if response.header.sequence != sequence {
+                    continue;
+                }
+                match response.header.msg_type as i32 {
+                    libc::NLMSG_DONE => return Ok(responses),
+                    libc::NLMSG_ERROR => {
+                        let code = response
+                            .payload
+                            .get(0..4)
+                            .map(|v| i32::from_ne_bytes(v.try_into().unwrap()))
+                            .unwrap_or(0);
+                        if code != 0 {
+                            return Err(NetworkError::os(
+                                operation,
+                                None,
+                                nix::errno::Errno::from_raw(-code),
+                            ));
+                        }
+                        return Ok(responses);
+                    }
+                    _ => responses.push(response),
+                }
+            }
+        }
+    }
+}
+
+/// Dumps every link in the namespace, returning the link message and its attributes.
+pub(crate) fn dump_links(
+    socket: &mut NetlinkSocket,
+) -> Result<Vec<(InterfaceInfoMessage, Vec<u8>)>, NetworkError> {
+    let mut message = NetlinkMessage::new(
+        libc::RTM_GETLINK,
+        libc::NLM_F_DUMP as u16,
+        &InterfaceInfoMessage::default(),
+    );
+
+    Ok(socket
+        .request(&mut message)?
+        .iter()
+        .filter(|response| response.header.msg_type == libc::RTM_NEWLINK)
+        .filter_map(|response| response.split::<InterfaceInfoMessage>())
+        .map(|(info, attrs)| (info, attrs.to_vec()))
+        .collect())
+}
+
+/// Names the request in errors, such as `RTM_NEWLINK`.
+fn message_name(msg_type: u16) -> String {
+    let name = match msg_type {
+        libc::RTM_NEWLINK => "RTM_NEWLINK",
+        libc::RTM_DELLINK => "RTM_DELLINK",
+        libc::RTM_GETLINK => "RTM_GETLINK",
+        libc::RTM_NEWADDR => "RTM_NEWADDR",
+        libc::RTM_DELADDR => "RTM_DELADDR",
+        libc::RTM_GETADDR => "RTM_GETADDR",
+        libc::RTM_NEWNEIGH => "RTM_NEWNEIGH",
+        libc::RTM_DELNEIGH => "RTM_DELNEIGH",
+        libc::RTM_GETNEIGH => "RTM_GETNEIGH",
+        other => return format!("netlink message {other}"),
+    };
+    name.to_owned()
+}
+
+pub(crate) fn index_of(name: &str) -> Result<u32, NetworkError> {
+    validate_name(name)?;
+    nix::net::if_::if_nametoindex(name).map_err(NetworkError::on("if_nametoindex", name))
+}
+
+fn address_family(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
+
+fn address_bytes(address: IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(v) => v.octets().to_vec(),
+        IpAddr::V6(v) => v.octets().to_vec(),
+    }
+}
+
+/// Configures interfaces through rtnetlink.
+///
+/// Unlike the ioctl backend this supports Ipv6, multiple addresses per interface, and creating
+/// or deleting links.
+pub struct NetlinkBackend {
+    socket: Mutex<NetlinkSocket>,
+}
+
+impl NetlinkBackend {
+    pub fn new() -> Result<Self, NetworkError> {
+        Ok(Self {
+            socket: Mutex::new(NetlinkSocket::new(0)?),
+        })
+    }
+
+    fn request(&self, message: &mut NetlinkMessage) -> Result<Vec<NetlinkResponse>, NetworkError> {
+        self.socket
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .request(message)
+    }
+
+    fn get_link(&self, name: &str) -> Result<(InterfaceInfoMessage, Vec<u8>), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_GETLINK, 0, &info);
+
+        self.request(&mut message)?
+            .iter()
+            .find(|response| response.header.msg_type == libc::RTM_NEWLINK)
+            .and_then(|response| response.split::<InterfaceInfoMessage>())
+            .map(|(info, attrs)| (info, attrs.to_vec()))
+            .ok_or_else(|| {
+                NetworkError::InvalidResponse(format!("No link information returned for '{name}'"))
+            })
+    }
+
+    /// Creates a new link of the given kind (e.g. `dummy` or `bridge`).
+    ///
+    /// Tun and tap devices cannot be created this way, use [`NetworkTun`](super::NetworkTun).
+    pub fn create_link(&self, name: &str, kind: &str) -> Result<(), NetworkError> {
+        validate_name(name)?;
+        let flags = (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16;
+        let mut message =
+            NetlinkMessage::new(libc::RTM_NEWLINK, flags, &InterfaceInfoMessage::default());
+        message
+            .attr_str(libc::IFLA_IFNAME, name)
+            .attr_nested(libc::IFLA_LINKINFO, |message| {
+                message.attr_str(libc::IFLA_INFO_KIND, kind);
+            });
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    pub fn delete_link(&self, name: &str) -> Result<(), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_DELLINK, 0, &info);
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    /// Moves the interface into `namespace`, see [`move_interface`](super::netns::move_interface).
+    pub fn move_to_namespace(
+        &self,
+        name: &str,
+        namespace: &NetNamespace,
+    ) -> Result<(), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+        message.attr(
+            libc::IFLA_NET_NS_FD,
+            &(namespace.as_raw_fd() as u32).to_ne_bytes(),
+        );
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    /// Lists every address assigned to the interface along with its prefix length.
+    pub fn addresses(&self, name: &str) -> Result<Vec<(IpAddr, u8)>, NetworkError> {
+        let index = index_of(name)?;
+        let mut message = NetlinkMessage::new(
+            libc::RTM_GETADDR,
+            libc::NLM_F_DUMP as u16,
+            &InterfaceAddressMessage::default(),
+        );
+
+        Ok(self
+            .request(&mut message)?
+            .iter()
+            .filter(|response| response.header.msg_type == libc::RTM_NEWADDR)
+            .filter_map(parse_address)
+            .filter(|(ifaddr, _)| ifaddr.index == index)
+            .map(|(ifaddr, address)| (address, ifaddr.prefix_len))
+            .collect())
+    }
+
+    fn neighbor_request(
+        &self,
+        msg_type: u16,
+        flags: u16,
+        name: &str,
+        address: IpAddr,
+        mac: Option<MacAddr>,
+    ) -> Result<(), NetworkError> {
+        let neighbor = NeighborMessage {
+            family: address_family(address),
+            index: index_of(name)? as i32,
+            state: libc::NUD_PERMANENT,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(msg_type, flags, &neighbor);
+        message.attr(libc::NDA_DST, &address_bytes(address));
+        if let Some(mac) = mac {
+            message.attr(libc::NDA_LLADDR, &mac.octets());
+        }
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    fn address_request(
+        &self,
+        msg_type: u16,
+        flags: u16,
+        name: &str,
+        address: IpAddr,
+        prefix_len: u8,
+    ) -> Result<(), NetworkError> {
+        validate_prefix_len(address, prefix_len)?;
+
+        let ifaddr = InterfaceAddressMessage {
+            family: address_family(address),
+            prefix_len,
+            index: index_of(name)?,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(msg_type, flags, &ifaddr);
+        message
+            .attr(libc::IFA_LOCAL, &address_bytes(address))
+            .attr(libc::IFA_ADDRESS, &address_bytes(address));
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+}
+
+/// The prefix length the kernel gives an Ipv4 address on a broadcast link when no mask is set,
+/// following the old address classes. `None` for addresses which can't be assigned this way.
+fn classful_prefix_len(address: Ipv4Addr) -> Option<u8> {
+    match address.octets()[0] {
+        _ if address.is_broadcast() => None,
+        0 | 224..=239 => None,
+        1..=127 => Some(8),
+        128..=191 => Some(16),
+        192..=223 => Some(24),
+        _ => Some(32),
+    }
+}
+
+impl NetworkBackend for NetlinkBackend {
+    fn flags(&self, name: &str) -> Result<InterfaceFlags, NetworkError> {
+        let (info, _) = self.get_link(name)?;
+        Ok(InterfaceFlags::from_bits_retain(info.flags))
+    }
+
+    fn set_flags(&self, name: &str, flags: InterfaceFlags) -> Result<(), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            flags: flags.bits(),
+            change: InterfaceFlags::all()
+                .difference(InterfaceFlags::READ_ONLY)
+                .bits(),
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    fn add_address(&self, name: &str, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+        let flags = (libc::NLM_F_CREATE | libc::NLM_F_EXCL) as u16;
+        self.address_request(libc::RTM_NEWADDR, flags, name, address, prefix_len)
+    }
+
+    /// Netlink always needs a prefix length, so this works out the one `SIOCSIFADDR` would use.
+    fn add_address_default_mask(&self, name: &str, address: Ipv4Addr) -> Result<u8, NetworkError> {
+        let prefix_len = match self.flags(name)?.contains(InterfaceFlags::POINTOPOINT) {
+            true => 32,
+            false => classful_prefix_len(address).ok_or_else(|| {
+                NetworkError::InvalidSubnetMask(format!(
+                    "Address has no default class mask, so a prefix length must be given (got '{address}')"
+                ))
+            })?,
+        };
+        self.add_address(name, IpAddr::V4(address), prefix_len)?;
+        Ok(prefix_len)
+    }
+
+    fn delete_address(
+        &self,
+        name: &str,
+        address: IpAddr,
+        prefix_len: u8,
+    ) -> Result<(), NetworkError> {
+        self.address_request(libc::RTM_DELADDR, 0, name, address, prefix_len)
+    }
+
+    fn mtu(&self, name: &str) -> Result<u32, NetworkError> {
+        let (_, attrs) = self.get_link(name)?;
+        let mtu = attributes(&attrs)
+            .find(|(kind, _)| *kind == libc::IFLA_MTU)
+            .and_then(|(_, data)| attr_u32(data));
+
+        mtu.ok_or_else(|| NetworkError::InvalidResponse(format!("No MTU returned for '{name}'")))
+    }
+
+    fn set_mtu(&self, name: &str, mtu: u32) -> Result<(), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+        message.attr(libc::IFLA_MTU, &mtu.to_ne_bytes());
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    fn tx_queue_len(&self, name: &str) -> Result<u32, NetworkError> {
+        let (_, attrs) = self.get_link(name)?;
+        let len = attributes(&attrs)
+            .find(|(kind, _)| *kind == libc::IFLA_TXQLEN)
+            .and_then(|(_, data)| attr_u32(data));
+
+        len.ok_or_else(|| {
+            NetworkError::InvalidResponse(format!("No tx queue length returned for '{name}'"))
+        })
+    }
+
+    fn set_tx_queue_len(&self, name: &str, len: u32) -> Result<(), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+        message.attr(libc::IFLA_TXQLEN, &len.to_ne_bytes());
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    fn hardware_address(&self, name: &str) -> Result<MacAddr, NetworkError> {
+        let (_, attrs) = self.get_link(name)?;
+        let address = attributes(&attrs)
+            .find(|(kind, _)| *kind == libc::IFLA_ADDRESS)
+            .and_then(|(_, data)| <[u8; 6]>::try_from(data).ok());
+
+        address.map(MacAddr::from).ok_or_else(|| {
+            NetworkError::InvalidResponse(format!("No Ethernet address returned for '{name}'"))
+        })
+    }
+
+    fn set_hardware_address(&self, name: &str, address: MacAddr) -> Result<(), NetworkError> {
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+        message.attr(libc::IFLA_ADDRESS, &address.octets());
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    fn add_neighbor(&self, name: &str, address: IpAddr, mac: MacAddr) -> Result<(), NetworkError> {
+        let flags = (libc::NLM_F_CREATE | libc::NLM_F_REPLACE) as u16;
+        self.neighbor_request(libc::RTM_NEWNEIGH, flags, name, address, Some(mac))
+    }
+
+    fn neighbor(&self, name: &str, address: IpAddr) -> Result<Option<MacAddr>, NetworkError> {
+        let index = index_of(name)? as i32;
+        let neighbor = NeighborMessage {
+            family: address_family(address),
+            ..Default::default()
+        };
+        // Dumped rather than fetched directly, as single neighbor lookups need kernel 4.20
+        let mut message =
+            NetlinkMessage::new(libc::RTM_GETNEIGH, libc::NLM_F_DUMP as u16, &neighbor);
+
+        let unusable = libc::NUD_INCOMPLETE | libc::NUD_FAILED;
+        for response in self.request(&mut message)? {
+            let Some((neighbor, attrs)) = response.split::<NeighborMessage>() else {
+                continue;
+            };
+            if neighbor.index != index || neighbor.state & unusable != 0 {
+                continue;
+            }
+
+            let mut dst = None;
+            let mut lladdr = None;
+            for (kind, data) in attributes(attrs) {
+                match kind {
+                    libc::NDA_DST => dst = attr_ip(neighbor.family, data),
+                    libc::NDA_LLADDR => lladdr = <[u8; 6]>::try_from(data).ok(),
+                    _ => {}
+                }
+            }
+            if dst == Some(address) {
+                return Ok(lladdr.map(MacAddr::new));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn delete_neighbor(&self, name: &str, address: IpAddr) -> Result<(), NetworkError> {
+        self.neighbor_request(libc::RTM_DELNEIGH, 0, name, address, None)
+    }
+
+    fn rename(&self, name: &str, new_name: &str) -> Result<(), NetworkError> {
+        validate_name(new_name)?;
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+        message.attr_str(libc::IFLA_IFNAME, new_name);
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+
+    fn alias(&self, name: &str) -> Result<String, NetworkError> {
+        let (_, attrs) = self.get_link(name)?;
+        let alias = attributes(&attrs)
+            .find(|(kind, _)| *kind == libc::IFLA_IFALIAS)
+            .map(|(_, data)| attr_str(data));
+
+        Ok(alias.unwrap_or_default())
+    }
+
+    fn set_alias(&self, name: &str, alias: &str) -> Result<(), NetworkError> {
+        validate_alias(alias)?;
+        let info = InterfaceInfoMessage {
+            index: index_of(name)? as i32,
+            ..Default::default()
+        };
+        let mut message = NetlinkMessage::new(libc::RTM_NEWLINK, 0, &info);
+        message.attr_str(libc::IFLA_IFALIAS, alias);
+
+        self.request(&mut message)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attrs_of(message: &NetlinkMessage) -> &[u8] {
+        let start = NLMSG_HDRLEN + std::mem::size_of::<InterfaceInfoMessage>();
+        &message.buf[start..]
+    }
+
+    #[test]
+    fn attributes_round_trip_with_padding() {
+        let mut message =
+            NetlinkMessage::new(libc::RTM_NEWLINK, 0, &InterfaceInfoMessage::default());
+        message
+            .attr(libc::IFLA_MTU, &1400u32.to_ne_bytes())
+            .attr_str(libc::IFLA_IFNAME, "tun0")
+            .attr(libc::IFLA_ADDRESS, &[2, 0, 0, 0, 0, 1])
+            .attr_nested(libc::IFLA_LINKINFO, |message| {
+                message.attr_str(libc::IFLA_INFO_KIND, "tun");
+            });
+        assert_eq!(message.msg_type(), libc::RTM_NEWLINK);
+        assert_eq!(message.buf.len() % 4, 0);
+
+        let attrs = attributes(attrs_of(&message)).collect::<Vec<_>>();
+        let kinds = attrs.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                libc::IFLA_MTU,
+                libc::IFLA_IFNAME,
+                libc::IFLA_ADDRESS,
+                libc::IFLA_LINKINFO
+            ]
+        );
+        assert_eq!(attr_u32(attrs[0].1), Some(1400));
+        assert_eq!(attr_str(attrs[1].1), "tun0");
+        assert_eq!(attrs[2].1, [2, 0, 0, 0, 0, 1]);
+
+        let nested = attributes(attrs[3].1).collect::<Vec<_>>();
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].0, libc::IFLA_INFO_KIND);
+        assert_eq!(attr_str(nested[0].1), "tun");
+    }
+
+    #[test]
+    fn attributes_stop_at_malformed_lengths() {
+        // A valid u32 attribute, then one claiming to be longer than the data left
+        let mut data = vec![8, 0, 4, 0, 1, 0, 0, 0];
+        data.extend([12, 0, 3, 0, 1, 2]);
+        let attrs = attributes(&data).collect::<Vec<_>>();
+        assert_eq!(attrs, [(4, &[1u8, 0, 0, 0][..])]);
+
+        // Lengths shorter than the header would otherwise never advance
+        assert_eq!(attributes(&[2, 0, 3, 0, 0, 0, 0, 0]).count(), 0);
+        assert_eq!(attributes(&[8, 0]).count(), 0);
+    }
+
+    #[test]
+    fn attribute_values_are_read_defensively() {
+        assert_eq!(attr_u32(&[1, 0]), None);
+        assert_eq!(attr_str(b"no terminator"), "no terminator");
+        assert_eq!(
+            attr_ip(libc::AF_INET as u8, &[10, 0, 0, 1]),
+            Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+        );
+        assert_eq!(attr_ip(libc::AF_INET6 as u8, &[0; 4]), None);
+        assert_eq!(classful_prefix_len(Ipv4Addr::new(10, 1, 2, 3)), Some(8));
+        assert_eq!(classful_prefix_len(Ipv4Addr::new(172, 16, 0, 1)), Some(16));
+        assert_eq!(classful_prefix_len(Ipv4Addr::new(192, 168, 1, 1)), Some(24));
+        assert_eq!(classful_prefix_len(Ipv4Addr::new(240, 0, 0, 1)), Some(32));
+        assert_eq!(classful_prefix_len(Ipv4Addr::new(224, 0, 0, 1)), None);
+        assert_eq!(classful_prefix_len(Ipv4Addr::new(0, 1, 2, 3)), None);
+        assert_eq!(classful_prefix_len(Ipv4Addr::BROADCAST), None);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    cell::Cell,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+        unix::fs::MetadataExt as _,
+    },
+    path::Path,
+    sync::Arc,
+};
+
+use nix::sched::{setns, CloneFlags};
+
+use super::error::NetworkError;
+
+thread_local! {
+    /// How many [`NetNamespace::enter`] calls the thread is inside which switched namespace.
+    static ENTERED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Whether the calling thread has been switched into another namespace by
+/// [`NetNamespace::enter`], in which case sysfs still shows the namespace it came from.
+pub(crate) fn entered() -> bool {
+    ENTERED.get() > 0
+}
+
+/// A handle to a network namespace.
+///
+/// Tuns built with a namespace (see [`NetworkTun::builder`](super::NetworkTun::builder)) live
+/// inside it, while the tun itself can still be read and written from any namespace.
+#[derive(Debug, Clone)]
+pub struct NetNamespace {
+    fd: Arc<OwnedFd>,
+}
+
+impl NetNamespace {
+    /// Opens a namespace created by `ip netns add`, found under `/run/netns`.
+    pub fn named(name: &str) -> Result<Self, NetworkError> {
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(NetworkError::InvalidName(format!(
+                "Namespace names cannot be empty or contain '/' (got '{name}')"
+            )));
+        }
+
+        Self::from_path(format!("/run/netns/{name}"))
+    }
+
+    /// Opens a namespace from a path such as `/proc/<pid>/ns/net`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, NetworkError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(NetworkError::call(&format!("opening {}", path.display())))?;
+
+        Ok(Self::from_fd(file.into()))
+    }
+
+    pub fn from_fd(fd: OwnedFd) -> Self {
+        Self { fd: Arc::new(fd) }
+    }
+
+    /// The namespace the calling thread is currently in.
+    pub fn current() -> Result<Self, NetworkError> {
+        Self::from_path("/proc/thread-self/ns/net")
+    }
+
+    /// Runs `f` with the calling thread switched into this namespace, switching back afterwards.
+    ///
+    /// Sockets and devices created by `f` stay in this namespace.
+    pub fn enter<T, F>(&self, f: F) -> Result<T, NetworkError>
+    where
+        F: FnOnce() -> Result<T, NetworkError>,
+    {
+        let original = Self::current()?;
+        let switched = !original.same_as(self)?;
+        setns(self.fd.as_fd(), CloneFlags::CLONE_NEWNET).map_err(NetworkError::call("setns"))?;
+        let guard = Restore(Some(original), switched);
+        if switched {
+            ENTERED.set(ENTERED.get() + 1);
+        }
+
+        let result = f();
+
+        guard.restore()?;
+        result
+    }
+
+    fn same_as(&self, other: &Self) -> Result<bool, NetworkError> {
+        let id = |ns: &Self| -> std::io::Result<(u64, u64)> {
+            let metadata = std::fs::File::from(ns.fd.try_clone()?).metadata()?;
+            Ok((metadata.dev(), metadata.ino()))
+        };
+        Ok(id(self).map_err(NetworkError::call("fstat"))?
+            == id(other).map_err(NetworkError::call("fstat"))?)
+    }
+}
+
+/// Switches the thread back into the namespace it was in, even if the closure run inside
+/// another one panics. Also tracks whether `enter` switched namespace at all.
+struct Restore(Option<NetNamespace>, bool);
+
+impl Restore {
+    fn restore(mut self) -> Result<(), NetworkError> {
+        match self.0.take() {
+            Some(original) => {
+                self.left();
+                setns(original.fd.as_fd(), CloneFlags::CLONE_NEWNET)
+                    .map_err(NetworkError::call("setns"))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn left(&self) {
+        if self.1 {
+            ENTERED.set(ENTERED.get() - 1);
+        }
+    }
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(original) = self.0.take() {
+            self.left();
+            // Only reached while unwinding, where carrying on in the wrong namespace would be
+            // worse than the abort a second panic causes
+            setns(original.fd.as_fd(), CloneFlags::CLONE_NEWNET)
+                .expect("failed to switch back to the original network namespace");
+        }
+    }
+}
+
+impl AsFd for NetNamespace {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for NetNamespace {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Moves an existing interface from the current namespace into `namespace`.
+///
+/// The interface loses its addresses and routes in the process, and any [`NetworkTun`] for it
+/// keeps configuring the namespace it was created in.
+///
+/// [`NetworkTun`]: super::NetworkTun
+#[cfg(feature = "netlink")]
+pub fn move_interface(name: &str, namespace: &NetNamespace) -> Result<(), NetworkError> {
+    super::netlink::NetlinkBackend::new()?.move_to_namespace(name, namespace)
+}
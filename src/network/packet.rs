@@ -2,7 +2,7 @@ use std::net::{Ipv4Addr, Ipv6Addr};
 
 use super::error::NetworkError;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum IpVersion {
     Ipv4 = 4,
@@ -123,6 +123,15 @@ impl IpPacket {
     }
 }
 
+impl IpPacket {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            IpPacket::Ipv4(v) => v.to_bytes(),
+            IpPacket::Ipv6(v) => v.to_bytes(),
+        }
+    }
+}
+
 impl std::fmt::Display for IpPacket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -156,6 +165,25 @@ pub struct Ipv4Packet {
     data: Vec<u8>,
 }
 
+impl Ipv4Packet {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.total_length as usize);
+        data.push(((self.version as u8) << 4) | (self.ihl / 4));
+        data.push((self.dscp << 2) | self.ecn);
+        data.extend_from_slice(&self.total_length.to_be_bytes());
+        data.extend_from_slice(&self.identification.to_be_bytes());
+        data.extend_from_slice(&(((self.flags as u16) << 13) | self.fragment_offset).to_be_bytes());
+        data.push(self.ttl);
+        data.push(self.protocol);
+        data.extend_from_slice(&self.header_checksum.to_be_bytes());
+        data.extend_from_slice(&self.source.octets());
+        data.extend_from_slice(&self.destination.octets());
+        data.extend_from_slice(&self.options);
+        data.extend_from_slice(&self.data);
+        data
+    }
+}
+
 impl std::fmt::Display for Ipv4Packet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -193,6 +221,23 @@ pub struct Ipv6Packet {
     data: Vec<u8>,
 }
 
+impl Ipv6Packet {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(40 + self.payload_length as usize);
+        data.push(((self.version as u8) << 4) | (self.traffic_class >> 4));
+        data.push((self.traffic_class << 4) | ((self.flow_label >> 16) as u8 & 0x0F));
+        data.extend_from_slice(&(self.flow_label as u16).to_be_bytes());
+        data.extend_from_slice(&self.payload_length.to_be_bytes());
+        data.push(self.next_header);
+        data.push(self.hop_limit);
+        data.extend_from_slice(&self.source.octets());
+        data.extend_from_slice(&self.destination.octets());
+        data.extend_from_slice(&self.extension_headers);
+        data.extend_from_slice(&self.data);
+        data
+    }
+}
+
 impl std::fmt::Display for Ipv6Packet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -0,0 +1,348 @@
+use std::sync::{Arc, Mutex};
+
+use super::{error::NetworkError, packet::IpPacket};
+
+/// How many free buffers a pool keeps by default, see [`PacketPool::max_retained`].
+pub const DEFAULT_MAX_RETAINED: usize = 1024;
+
+#[derive(Debug)]
+struct PoolInner {
+    mtu: usize,
+    headroom: usize,
+    tailroom: usize,
+    max_retained: usize,
+    free: Mutex<Vec<Box<[u8]>>>,
+}
+
+/// A pool of fixed-size packet buffers which are reused instead of allocated for every packet.
+///
+/// Each buffer holds `mtu` bytes of packet with `headroom` bytes reserved in front and `tailroom`
+/// bytes behind, so framing headers and trailers can be added in place. Cloning the pool is cheap
+/// and every clone shares the same buffers.
+///
+/// Buffers returned beyond [`PacketPool::max_retained`] are freed, so a burst doesn't keep its
+/// memory forever.
+#[derive(Debug, Clone)]
+pub struct PacketPool {
+    inner: Arc<PoolInner>,
+}
+
+impl PacketPool {
+    pub fn new(mtu: usize) -> Self {
+        Self::with_room(mtu, 0, 0, DEFAULT_MAX_RETAINED)
+    }
+
+    fn with_room(mtu: usize, headroom: usize, tailroom: usize, max_retained: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                mtu,
+                headroom,
+                tailroom,
+                max_retained,
+                free: Mutex::new(vec![]),
+            }),
+        }
+    }
+
+    /// Reserves `headroom` bytes in front of every packet. Buffers already in the pool are
+    /// dropped.
+    pub fn headroom(self, headroom: usize) -> Self {
+        let inner = &self.inner;
+        Self::with_room(inner.mtu, headroom, inner.tailroom, inner.max_retained)
+    }
+
+    /// Reserves `tailroom` bytes behind every packet. Buffers already in the pool are dropped.
+    pub fn tailroom(self, tailroom: usize) -> Self {
+        let inner = &self.inner;
+        Self::with_room(inner.mtu, inner.headroom, tailroom, inner.max_retained)
+    }
+
+    /// Keeps at most `max_retained` free buffers, freeing any returned beyond that. Defaults to
+    /// [`DEFAULT_MAX_RETAINED`]. Buffers already in the pool are dropped.
+    pub fn max_retained(self, max_retained: usize) -> Self {
+        let inner = &self.inner;
+        Self::with_room(inner.mtu, inner.headroom, inner.tailroom, max_retained)
+    }
+
+    /// Allocates `count` buffers up front so the first packets don't have to, up to the
+    /// maximum the pool retains.
+    pub fn preallocate(self, count: usize) -> Self {
+        let count = count.min(self.inner.max_retained.saturating_sub(self.available()));
+        let buffers = (0..count).map(|_| self.allocate()).collect::<Vec<_>>();
+        self.lock().extend(buffers);
+        self
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.inner.mtu
+    }
+
+    /// Number of buffers currently waiting in the pool.
+    pub fn available(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Takes an empty packet from the pool, allocating a new buffer if none are free.
+    pub fn get(&self) -> PooledPacket {
+        let data = self.lock().pop().unwrap_or_else(|| self.allocate());
+
+        PooledPacket {
+            data: Some(data),
+            start: self.inner.headroom,
+            end: self.inner.headroom,
+            pool: self.inner.clone(),
+        }
+    }
+
+    /// A copy of this pool's layout with a different MTU, sharing none of its buffers.
+    pub(crate) fn resized(&self, mtu: usize) -> Self {
+        let inner = &self.inner;
+        Self::with_room(mtu, inner.headroom, inner.tailroom, inner.max_retained)
+    }
+
+    fn allocate(&self) -> Box<[u8]> {
+        vec![0; self.inner.headroom + self.inner.mtu + self.inner.tailroom].into_boxed_slice()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Box<[u8]>>> {
+        self.inner.free.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A packet held in a buffer borrowed from a [`PacketPool`], returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledPacket {
+    // Only `None` while being dropped
+    data: Option<Box<[u8]>>,
+    start: usize,
+    end: usize,
+    pool: Arc<PoolInner>,
+}
+
+impl PooledPacket {
+    fn buffer(&self) -> &[u8] {
+        self.data.as_deref().unwrap_or_default()
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        self.data.as_deref_mut().unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Bytes free in front of the packet.
+    pub fn headroom(&self) -> usize {
+        self.start
+    }
+
+    /// Bytes free behind the packet.
+    pub fn tailroom(&self) -> usize {
+        self.buffer().len() - self.end
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer()[self.start..self.end]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        let (start, end) = (self.start, self.end);
+        &mut self.buffer_mut()[start..end]
+    }
+
+    /// Empties the packet, restoring the pool's headroom.
+    pub fn clear(&mut self) {
+        self.start = self.pool.headroom;
+        self.end = self.pool.headroom;
+    }
+
+    /// Replaces the contents of the packet with `data`, keeping the pool's headroom.
+    pub fn fill(&mut self, data: &[u8]) -> Result<(), NetworkError> {
+        self.clear();
+        self.push_back(data)
+    }
+
+    /// Prepends `header` using the headroom.
+    pub fn push_front(&mut self, header: &[u8]) -> Result<(), NetworkError> {
+        if header.len() > self.headroom() {
+            return Err(NetworkError::PacketParseError(format!(
+                "header does not fit in headroom (header: {}, headroom: {})",
+                header.len(),
+                self.headroom()
+            )));
+        }
+        let start = self.start - header.len();
+        self.buffer_mut()[start..start + header.len()].copy_from_slice(header);
+        self.start = start;
+        Ok(())
+    }
+
+    /// Appends `trailer` using the tailroom.
+    pub fn push_back(&mut self, trailer: &[u8]) -> Result<(), NetworkError> {
+        if trailer.len() > self.tailroom() {
+            return Err(NetworkError::PacketParseError(format!(
+                "data does not fit in tailroom (data: {}, tailroom: {})",
+                trailer.len(),
+                self.tailroom()
+            )));
+        }
+        let end = self.end;
+        self.buffer_mut()[end..end + trailer.len()].copy_from_slice(trailer);
+        self.end += trailer.len();
+        Ok(())
+    }
+
+    /// Removes and returns the first `len` bytes, such as a framing header.
+    pub fn pull_front(&mut self, len: usize) -> Result<&[u8], NetworkError> {
+        if len > self.len() {
+            return Err(NetworkError::PacketParseError(format!(
+                "cannot remove more than the packet holds (requested: {len}, length: {})",
+                self.len()
+            )));
+        }
+        self.start += len;
+        Ok(&self.buffer()[self.start - len..self.start])
+    }
+
+    /// Shortens the packet to `len` bytes, returning the rest to the tailroom.
+    pub fn truncate(&mut self, len: usize) {
+        self.end = self.start + len.min(self.len());
+    }
+
+    pub fn parse(&self) -> Result<IpPacket, NetworkError> {
+        IpPacket::from_bytes(self.as_slice())
+    }
+
+    /// The space where the packet goes, from the current start up to the pool's MTU.
+    pub(crate) fn spare_mut(&mut self) -> &mut [u8] {
+        let start = self.start;
+        let end = self.pool.headroom + self.pool.mtu;
+        &mut self.buffer_mut()[start..end.max(start)]
+    }
+
+    pub(crate) fn set_len(&mut self, len: usize) {
+        self.end = (self.start + len).min(self.buffer().len());
+    }
+}
+
+impl std::ops::Deref for PooledPacket {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl std::ops::DerefMut for PooledPacket {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
+impl Drop for PooledPacket {
+    fn drop(&mut self) {
+        if let Some(data) = self.data.take() {
+            let mut free = self.pool.free.lock().unwrap_or_else(|e| e.into_inner());
+            if free.len() < self.pool.max_retained {
+                free.push(data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_keep_their_headroom_and_tailroom() {
+        let pool = PacketPool::new(8).headroom(4).tailroom(2);
+        let mut packet = pool.get();
+        assert!(packet.is_empty());
+        assert_eq!((packet.headroom(), packet.tailroom()), (4, 10));
+
+        packet.fill(&[1, 2, 3]).unwrap();
+        assert_eq!(packet.as_slice(), [1, 2, 3]);
+        assert_eq!((packet.headroom(), packet.tailroom()), (4, 7));
+
+        packet.push_front(&[9, 9]).unwrap();
+        packet.push_back(&[7]).unwrap();
+        assert_eq!(packet.as_slice(), [9, 9, 1, 2, 3, 7]);
+        assert_eq!(packet.headroom(), 2);
+
+        assert_eq!(packet.pull_front(2).unwrap(), [9, 9]);
+        assert_eq!(packet.as_slice(), [1, 2, 3, 7]);
+        packet.truncate(3);
+        assert_eq!(packet.as_slice(), [1, 2, 3]);
+        packet.truncate(10);
+        assert_eq!(packet.len(), 3);
+
+        // Refilling restores the headroom taken by push_front and pull_front
+        packet.push_front(&[0; 4]).unwrap();
+        packet.fill(&[5]).unwrap();
+        assert_eq!((packet.headroom(), packet.as_slice()), (4, &[5][..]));
+    }
+
+    #[test]
+    fn room_is_never_exceeded() {
+        let pool = PacketPool::new(4).headroom(2);
+        let mut packet = pool.get();
+        packet.fill(&[1, 2, 3, 4]).unwrap();
+
+        assert!(packet.push_front(&[0; 3]).is_err());
+        assert!(packet.push_back(&[0]).is_err());
+        assert!(packet.pull_front(5).is_err());
+        assert!(packet.fill(&[0; 5]).is_err());
+        assert_eq!(packet.len(), 0, "fill empties the packet before checking");
+        assert_eq!(packet.spare_mut().len(), 4);
+    }
+
+    #[test]
+    fn dropped_packets_return_to_the_pool() {
+        let pool = PacketPool::new(16).preallocate(2);
+        assert_eq!(pool.available(), 2);
+
+        let first = pool.get();
+        let second = pool.get();
+        let third = pool.get();
+        assert_eq!(pool.available(), 0);
+
+        drop((first, second, third));
+        assert_eq!(pool.available(), 3);
+
+        // Reused buffers come back empty
+        let mut packet = pool.get();
+        packet.fill(&[1; 16]).unwrap();
+        drop(packet);
+        assert!(pool.get().is_empty());
+    }
+
+    #[test]
+    fn only_max_retained_buffers_are_kept() {
+        let pool = PacketPool::new(16).max_retained(2).preallocate(5);
+        assert_eq!(pool.available(), 2);
+
+        let burst = (0..4).map(|_| pool.get()).collect::<Vec<_>>();
+        assert_eq!(pool.available(), 0);
+        drop(burst);
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    fn resized_pools_share_no_buffers() {
+        let pool = PacketPool::new(16).headroom(4).preallocate(1);
+        let resized = pool.resized(32);
+        assert_eq!((resized.mtu(), resized.available()), (32, 0));
+
+        let mut packet = resized.get();
+        assert_eq!(packet.headroom(), 4);
+        assert_eq!(packet.spare_mut().len(), 32);
+        drop(packet);
+        assert_eq!((pool.available(), resized.available()), (1, 1));
+    }
+}
@@ -0,0 +1,225 @@
+use std::os::{
+    fd::{AsRawFd as _, FromRawFd as _, OwnedFd},
+    unix::fs::MetadataExt as _,
+};
+
+use super::{consts, error::NetworkError};
+
+/// Whether `/dev/net/tun` can be opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceAccess {
+    Available,
+    /// The tun module isn't loaded, or `/dev/net/tun` isn't present in this container.
+    Missing,
+    /// The device exists but isn't readable and writable by this user.
+    PermissionDenied,
+    /// Opening the device failed for some other reason.
+    Failed,
+}
+
+/// What stands in the way of creating and configuring tuns from the calling thread.
+///
+/// Checks the network namespace the thread is currently in, use
+/// [`NetNamespace::enter`](super::netns::NetNamespace::enter) to check another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preflight {
+    pub device: DeviceAccess,
+    /// Whether `CAP_NET_ADMIN` is in the effective capability set.
+    pub net_admin: bool,
+    /// Whether the network namespace belongs to our user namespace or one created below it,
+    /// which is the only place our capabilities apply.
+    pub owns_namespace: bool,
+}
+
+impl Preflight {
+    pub fn check() -> Self {
+        let device = match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")
+        {
+            Ok(_) => DeviceAccess::Available,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DeviceAccess::Missing,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                DeviceAccess::PermissionDenied
+            }
+            Err(_) => DeviceAccess::Failed,
+        };
+
+        // Anything we can't find out counts as a problem, it's only used to explain failures
+        Self {
+            device,
+            net_admin: has_net_admin().unwrap_or(false),
+            owns_namespace: owns_namespace().unwrap_or(false),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// A description of each problem found, along with how to fix it.
+    pub fn problems(&self) -> Vec<&'static str> {
+        let mut problems = vec![];
+        match self.device {
+            DeviceAccess::Available => {}
+            DeviceAccess::Missing => problems.push(
+                "/dev/net/tun does not exist, load the tun module with `modprobe tun` or pass \
+                 the device into the container",
+            ),
+            DeviceAccess::PermissionDenied => problems.push(
+                "/dev/net/tun is not readable and writable by this user, check its permissions",
+            ),
+            DeviceAccess::Failed => problems.push("/dev/net/tun could not be opened"),
+        }
+        if !self.net_admin {
+            problems.push(
+                "the process lacks CAP_NET_ADMIN, run it as root or grant the capability with \
+                 `setcap cap_net_admin+ep <binary>`",
+            );
+        }
+        if !self.owns_namespace {
+            problems.push(
+                "the network namespace belongs to a user namespace we have no capabilities in, \
+                 create the tun from a network namespace made inside our own user namespace",
+            );
+        }
+        problems
+    }
+
+    /// Attaches a diagnosis to permission errors, leaving others and unexplained ones alone.
+    pub(crate) fn explain(error: NetworkError) -> NetworkError {
+        Self::explain_with(error, Self::check)
+    }
+
+    /// Like [`Preflight::explain`], only running `check` for permission errors.
+    fn explain_with(error: NetworkError, check: impl FnOnce() -> Self) -> NetworkError {
+        if !error.is_permission_denied() {
+            return error;
+        }
+
+        let problems = check().problems();
+        if problems.is_empty() {
+            return error;
+        }
+        NetworkError::PermissionDenied {
+            diagnosis: problems.join("; "),
+            source: Box::new(error),
+        }
+    }
+}
+
+fn has_net_admin() -> Option<bool> {
+    let status = std::fs::read_to_string("/proc/thread-self/status").ok()?;
+    let caps = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?
+        .trim();
+    let caps = u64::from_str_radix(caps, 16).ok()?;
+
+    Some(caps & (1 << consts::CAP_NET_ADMIN) != 0)
+}
+
+fn ns_ioctl(fd: &OwnedFd, request: u64) -> nix::Result<OwnedFd> {
+    let code = unsafe { nix::libc::ioctl(fd.as_raw_fd(), request) };
+    let fd = nix::errno::Errno::result(code)?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn ns_id(fd: &OwnedFd) -> Option<(u64, u64)> {
+    let metadata = std::fs::File::from(fd.try_clone().ok()?).metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Walks up from the user namespace owning our network namespace, looking for our own.
+fn owns_namespace() -> Option<bool> {
+    let ours = ns_id(
+        &std::fs::File::open("/proc/thread-self/ns/user")
+            .ok()?
+            .into(),
+    )?;
+    let net: OwnedFd = std::fs::File::open("/proc/thread-self/ns/net").ok()?.into();
+
+    let mut user = ns_ioctl(&net, consts::NS_GET_USERNS).ok()?;
+    loop {
+        if ns_id(&user)? == ours {
+            return Some(true);
+        }
+        // EPERM once the parent lies outside our user namespace, so it can't be ours
+        match ns_ioctl(&user, consts::NS_GET_PARENT) {
+            Ok(parent) => user = parent,
+            Err(nix::errno::Errno::EPERM) => return Some(false),
+            Err(_) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::errno::Errno;
+
+    use super::*;
+
+    const READY: Preflight = Preflight {
+        device: DeviceAccess::Available,
+        net_admin: true,
+        owns_namespace: true,
+    };
+
+    fn denied(errno: Errno) -> NetworkError {
+        NetworkError::os("TUNSETIFF", Some("tun0"), errno)
+    }
+
+    #[test]
+    fn each_problem_is_described() {
+        assert!(READY.is_ready());
+
+        let preflight = Preflight {
+            device: DeviceAccess::Missing,
+            net_admin: false,
+            owns_namespace: false,
+        };
+        let problems = preflight.problems();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("modprobe tun"));
+        assert!(problems[1].contains("CAP_NET_ADMIN"));
+        assert!(problems[2].contains("user namespace"));
+        assert!(!preflight.is_ready());
+    }
+
+    #[test]
+    fn permission_errors_get_a_diagnosis() {
+        let lacking = || Preflight {
+            net_admin: false,
+            ..READY
+        };
+
+        for errno in [Errno::EPERM, Errno::EACCES] {
+            let error = Preflight::explain_with(denied(errno), lacking);
+            let NetworkError::PermissionDenied { diagnosis, source } = &error else {
+                panic!("{error:?} was not explained");
+            };
+            assert!(diagnosis.contains("CAP_NET_ADMIN"));
+            assert!(matches!(source.as_ref(), NetworkError::Os { .. }));
+            // The errno survives the wrapping, so callers can still match on it
+            assert_eq!(error.errno(), Some(errno));
+        }
+    }
+
+    #[test]
+    fn other_errors_are_left_alone() {
+        let error = Preflight::explain_with(denied(Errno::EBUSY), || {
+            panic!("only permission errors are checked")
+        });
+        assert_eq!(error.errno(), Some(Errno::EBUSY));
+
+        let error = Preflight::explain_with(NetworkError::Unsupported("tap".to_string()), || {
+            panic!("only permission errors are checked")
+        });
+        assert!(matches!(error, NetworkError::Unsupported(_)));
+
+        // Nothing to explain when the preflight finds no problem
+        let error = Preflight::explain_with(denied(Errno::EPERM), || READY);
+        assert!(matches!(error, NetworkError::Os { .. }));
+    }
+}
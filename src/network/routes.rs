@@ -0,0 +1,238 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::AsRawFd as _,
+};
+
+use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+
+use super::{
+    consts,
+    error::NetworkError,
+    settings::{NetworkRouteIpv4, NetworkRouteIpv6},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub destination: IpAddr,
+    pub prefix_len: u8,
+    pub gateway: Option<IpAddr>,
+    pub metric: u32,
+    pub device: Option<String>,
+}
+
+impl Route {
+    pub fn new(destination: IpAddr, prefix_len: u8) -> Self {
+        Self {
+            destination,
+            prefix_len,
+            gateway: None,
+            metric: 0,
+            device: None,
+        }
+    }
+
+    pub fn gateway(mut self, gateway: IpAddr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    pub fn metric(mut self, metric: u32) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_owned());
+        self
+    }
+}
+
+impl std::fmt::Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.destination, self.prefix_len)?;
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {gateway}")?;
+        }
+        if let Some(device) = &self.device {
+            write!(f, " dev {device}")?;
+        }
+        write!(f, " metric {}", self.metric)
+    }
+}
+
+fn route_ioctl_ipv4(
+    route: &Route,
+    destination: Ipv4Addr,
+    gateway: Option<Ipv4Addr>,
+    (request, operation): (u64, &str),
+) -> Result<(), NetworkError> {
+    let entry = NetworkRouteIpv4::new(
+        destination,
+        route.prefix_len,
+        gateway,
+        route.metric,
+        route.device.as_deref(),
+    )?;
+
+    let sock_fd = socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::empty(),
+        None,
+    )
+    .map_err(NetworkError::call("ioctl socket"))?;
+
+    let code = unsafe { nix::libc::ioctl(sock_fd.as_raw_fd(), request, entry.as_ptr()) };
+    nix::errno::Errno::result(code)
+        .map_err(|e| NetworkError::os(operation, route.device.as_deref(), e))?;
+
+    Ok(())
+}
+
+fn route_ioctl_ipv6(
+    route: &Route,
+    destination: Ipv6Addr,
+    gateway: Option<Ipv6Addr>,
+    (request, operation): (u64, &str),
+) -> Result<(), NetworkError> {
+    let ifindex = match &route.device {
+        Some(device) => nix::net::if_::if_nametoindex(device.as_str())
+            .map_err(NetworkError::on("if_nametoindex", device))?,
+        None => 0,
+    };
+    let entry = NetworkRouteIpv6::new(
+        destination,
+        route.prefix_len,
+        gateway,
+        route.metric,
+        ifindex,
+    )?;
+
+    let sock_fd = socket(
+        AddressFamily::Inet6,
+        SockType::Datagram,
+        SockFlag::empty(),
+        None,
+    )
+    .map_err(NetworkError::call("ioctl socket"))?;
+
+    let code = unsafe { nix::libc::ioctl(sock_fd.as_raw_fd(), request, entry.as_ptr()) };
+    nix::errno::Errno::result(code)
+        .map_err(|e| NetworkError::os(operation, route.device.as_deref(), e))?;
+
+    Ok(())
+}
+
+fn route_ioctl(route: &Route, request: (u64, &str)) -> Result<(), NetworkError> {
+    match (route.destination, route.gateway) {
+        (IpAddr::V4(destination), None) => route_ioctl_ipv4(route, destination, None, request),
+        (IpAddr::V4(destination), Some(IpAddr::V4(gateway))) => {
+            route_ioctl_ipv4(route, destination, Some(gateway), request)
+        }
+        (IpAddr::V6(destination), None) => route_ioctl_ipv6(route, destination, None, request),
+        (IpAddr::V6(destination), Some(IpAddr::V6(gateway))) => {
+            route_ioctl_ipv6(route, destination, Some(gateway), request)
+        }
+        _ => Err(NetworkError::InvalidRoute(format!(
+            "Route destination and gateway must be the same address family (got '{route}')"
+        ))),
+    }
+}
+
+pub fn add_route(route: &Route) -> Result<(), NetworkError> {
+    route_ioctl(route, (consts::SIOCADDRT, "SIOCADDRT"))
+}
+
+pub fn delete_route(route: &Route) -> Result<(), NetworkError> {
+    route_ioctl(route, (consts::SIOCDELRT, "SIOCDELRT"))
+}
+
+/// Lists the Ipv4 and Ipv6 routes known to the kernel, as reported by `/proc/net`.
+///
+/// Reads the namespace of the calling thread rather than that of the process.
+pub fn list_routes() -> Result<Vec<Route>, NetworkError> {
+    let mut routes = list_ipv4_routes()?;
+    routes.extend(list_ipv6_routes()?);
+    Ok(routes)
+}
+
+fn read_proc(path: &str) -> Result<String, NetworkError> {
+    std::fs::read_to_string(path).map_err(NetworkError::call(&format!("reading {path}")))
+}
+
+fn parse_error(path: &str, line: &str) -> NetworkError {
+    NetworkError::InvalidResponse(format!("Could not parse line from {path} (got '{line}')"))
+}
+
+fn list_ipv4_routes() -> Result<Vec<Route>, NetworkError> {
+    const PATH: &str = "/proc/thread-self/net/route";
+
+    // Iface Destination Gateway Flags RefCnt Use Metric Mask MTU Window IRTT
+    read_proc(PATH)?
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 8 {
+                return Err(parse_error(PATH, line));
+            }
+
+            let hex_addr = |field: &str| {
+                u32::from_str_radix(field, 16)
+                    .map(|v| Ipv4Addr::from(v.to_ne_bytes()))
+                    .map_err(|_| parse_error(PATH, line))
+            };
+
+            let gateway = hex_addr(fields[2])?;
+            let metric = fields[6].parse().map_err(|_| parse_error(PATH, line))?;
+            let mask = hex_addr(fields[7])?;
+
+            Ok(Route {
+                destination: IpAddr::V4(hex_addr(fields[1])?),
+                prefix_len: u32::from(mask).count_ones() as u8,
+                gateway: (!gateway.is_unspecified()).then_some(IpAddr::V4(gateway)),
+                metric,
+                device: Some(fields[0].to_owned()),
+            })
+        })
+        .collect()
+}
+
+fn list_ipv6_routes() -> Result<Vec<Route>, NetworkError> {
+    const PATH: &str = "/proc/thread-self/net/ipv6_route";
+
+    // Ipv6 may be disabled entirely, in which case there is simply nothing to list
+    if !std::path::Path::new(PATH).exists() {
+        return Ok(vec![]);
+    }
+
+    // dest dest_len src src_len gateway metric refcnt use flags iface
+    read_proc(PATH)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 10 {
+                return Err(parse_error(PATH, line));
+            }
+
+            let hex_addr = |field: &str| {
+                u128::from_str_radix(field, 16)
+                    .map(Ipv6Addr::from)
+                    .map_err(|_| parse_error(PATH, line))
+            };
+
+            let gateway = hex_addr(fields[4])?;
+
+            Ok(Route {
+                destination: IpAddr::V6(hex_addr(fields[0])?),
+                prefix_len: u8::from_str_radix(fields[1], 16)
+                    .map_err(|_| parse_error(PATH, line))?,
+                gateway: (!gateway.is_unspecified()).then_some(IpAddr::V6(gateway)),
+                metric: u32::from_str_radix(fields[5], 16).map_err(|_| parse_error(PATH, line))?,
+                device: Some(fields[9].to_owned()),
+            })
+        })
+        .collect()
+}
@@ -1,6 +1,10 @@
-use std::net::Ipv4Addr;
+use std::{
+    ffi::CString,
+    net::{Ipv4Addr, Ipv6Addr},
+    os::fd::AsRawFd,
+};
 
-use super::{consts, error::NetworkError};
+use super::{consts, error::NetworkError, mac::MacAddr};
 
 pub(crate) fn validate_name(name: &str) -> Result<[u8; 16], NetworkError> {
     let mut c_name = [0; 16];
@@ -22,125 +26,387 @@ pub(crate) fn validate_name(name: &str) -> Result<[u8; 16], NetworkError> {
     Ok(c_name)
 }
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct NetworkCreateTunTap {
-    network_name: [u8; 16],
-    flags: u16,
-    _0: [u8; 22],
-}
+/// `struct ifreq`, the argument to every `SIOC*` interface ioctl and to `TUNSETIFF`.
+///
+/// Only the name is set on creation, the union is filled in by the setter matching the request.
+#[repr(transparent)]
+pub struct IfReq(nix::libc::ifreq);
 
-#[allow(dead_code)]
-impl NetworkCreateTunTap {
-    pub fn new_tun(name: &str) -> Result<Self, NetworkError> {
-        Ok(Self {
-            network_name: validate_name(name)?,
-            flags: consts::IFF_TUN,
-            _0: [0; 22],
-        })
+// The kernel copies exactly this many bytes in and out, so a mismatch would corrupt the stack
+#[cfg(target_pointer_width = "64")]
+const _: () = assert!(std::mem::size_of::<nix::libc::ifreq>() == 40);
+#[cfg(target_pointer_width = "32")]
+const _: () = assert!(std::mem::size_of::<nix::libc::ifreq>() == 32);
+
+impl IfReq {
+    pub fn new(name: &str) -> Result<Self, NetworkError> {
+        let mut ifreq: nix::libc::ifreq = unsafe { std::mem::zeroed() };
+        for (c_name, c) in ifreq.ifr_name.iter_mut().zip(validate_name(name)?) {
+            *c_name = c as nix::libc::c_char;
+        }
+        Ok(Self(ifreq))
     }
 
-    pub fn new_tap(name: &str) -> Result<Self, NetworkError> {
-        Ok(Self {
-            network_name: validate_name(name)?,
-            flags: consts::IFF_TAP,
-            _0: [0; 22],
-        })
+    /// An ifreq without a name, for `TUNSETIFF` to pick one.
+    pub fn unnamed() -> Self {
+        Self(unsafe { std::mem::zeroed() })
     }
 
-    pub fn new_tun_no_name() -> Result<Self, NetworkError> {
-        Ok(Self {
-            network_name: [0; 16],
-            flags: consts::IFF_TUN,
-            _0: [0; 22],
-        })
+    pub fn name(&self) -> String {
+        self.0
+            .ifr_name
+            .iter()
+            .take_while(|&&x| x != 0)
+            .map(|&x| x as u8 as char)
+            .collect()
     }
 
-    pub fn new_tap_no_name() -> Result<Self, NetworkError> {
-        Ok(Self {
-            network_name: [0; 16],
-            flags: consts::IFF_TAP,
-            _0: [0; 22],
-        })
+    pub fn flags(&self) -> u16 {
+        unsafe { self.0.ifr_ifru.ifru_flags as u16 }
+    }
+
+    pub fn set_flags(&mut self, flags: u16) {
+        self.0.ifr_ifru.ifru_flags = flags as nix::libc::c_short;
+    }
+
+    /// The integer member, which is the MTU, tx queue length or index depending on the request.
+    pub fn value(&self) -> u32 {
+        unsafe { self.0.ifr_ifru.ifru_mtu as u32 }
+    }
+
+    pub fn set_value(&mut self, value: u32) {
+        self.0.ifr_ifru.ifru_mtu = value as nix::libc::c_int;
     }
 
-    pub fn get_name(&self) -> String {
-        self.network_name
+    /// The address member, as filled in by the address and netmask requests.
+    pub fn ipv4(&self) -> Ipv4Addr {
+        let addr = unsafe {
+            std::mem::transmute::<nix::libc::sockaddr, nix::libc::sockaddr_in>(
+                self.0.ifr_ifru.ifru_addr,
+            )
+        };
+        Ipv4Addr::from(addr.sin_addr.s_addr.to_ne_bytes())
+    }
+
+    /// Sets the address member, used for the address and netmask requests.
+    pub fn set_ipv4(&mut self, address: Ipv4Addr) {
+        self.0.ifr_ifru.ifru_addr = sockaddr_in(address);
+    }
+
+    pub fn hardware_address(&self) -> MacAddr {
+        let data = unsafe { self.0.ifr_ifru.ifru_hwaddr.sa_data };
+        let mut octets = [0; 6];
+        for (octet, x) in octets.iter_mut().zip(data) {
+            *octet = x as u8;
+        }
+        MacAddr::new(octets)
+    }
+
+    /// Sets the hardware address member. Link-layer multicast entries expect `AF_UNSPEC` as
+    /// the family, while changing the interface's own address expects `ARPHRD_ETHER`.
+    pub fn set_hardware_address(&mut self, family: u16, address: MacAddr) {
+        let mut hwaddr: nix::libc::sockaddr = unsafe { std::mem::zeroed() };
+        hwaddr.sa_family = family;
+        for (x, octet) in hwaddr.sa_data.iter_mut().zip(address.octets()) {
+            *x = octet as nix::libc::c_char;
+        }
+        self.0.ifr_ifru.ifru_hwaddr = hwaddr;
+    }
+
+    pub fn set_new_name(&mut self, new_name: &str) -> Result<(), NetworkError> {
+        let mut c_name = [0; 16];
+        for (x, c) in c_name.iter_mut().zip(validate_name(new_name)?) {
+            *x = c as nix::libc::c_char;
+        }
+        self.0.ifr_ifru.ifru_newname = c_name;
+        Ok(())
+    }
+
+    /// Whether the driver reports carrier, through `ETHTOOL_GLINK`.
+    pub(crate) fn ethtool_link(&mut self, fd: &impl AsRawFd) -> Result<bool, NetworkError> {
+        // struct ethtool_value
+        let mut value: [u32; 2] = [consts::ETHTOOL_GLINK, 0];
+        self.0.ifr_ifru.ifru_data = value.as_mut_ptr() as *mut nix::libc::c_char;
+        self.ioctl(fd, (consts::SIOCETHTOOL, "ETHTOOL_GLINK"))?;
+        Ok(value[1] != 0)
+    }
+
+    /// The name of the driver behind the interface, through `ETHTOOL_GDRVINFO`.
+    pub(crate) fn ethtool_driver(&mut self, fd: &impl AsRawFd) -> Result<String, NetworkError> {
+        // struct ethtool_drvinfo is 196 bytes, with the driver name right after the command
+        let mut info = [0u32; 49];
+        info[0] = consts::ETHTOOL_GDRVINFO;
+        self.0.ifr_ifru.ifru_data = info.as_mut_ptr() as *mut nix::libc::c_char;
+        self.ioctl(fd, (consts::SIOCETHTOOL, "ETHTOOL_GDRVINFO"))?;
+
+        let driver = info[1..9]
             .iter()
-            .filter(|&&x| x != 0)
-            .map(|&x| x as char)
-            .collect::<String>()
+            .flat_map(|x| x.to_ne_bytes())
+            .take_while(|&x| x != 0)
+            .map(char::from)
+            .collect();
+        Ok(driver)
+    }
+
+    /// Issues `request` on `fd` with this ifreq, which the kernel may write results into.
+    ///
+    /// Only for requests whose argument is an ifreq, others would have the kernel read pointers
+    /// out of it.
+    pub(crate) fn ioctl(
+        &mut self,
+        fd: &impl AsRawFd,
+        (request, operation): (u64, &str),
+    ) -> Result<(), NetworkError> {
+        let code = unsafe {
+            nix::libc::ioctl(
+                fd.as_raw_fd(),
+                request,
+                &mut self.0 as *mut nix::libc::ifreq,
+            )
+        };
+        nix::errno::Errno::result(code).map_err(|e| {
+            let name = self.name();
+            NetworkError::os(operation, Some(name.as_str()).filter(|x| !x.is_empty()), e)
+        })?;
+
+        Ok(())
     }
 }
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct NetworkSetIpv4Address {
-    network_name: [u8; 16],
-    address_family: u16,
-    _0: [u8; 2],
-    ip_address: Ipv4Addr,
-    _1: [u8; 16],
+pub(crate) fn prefix_to_mask(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
 }
 
-impl NetworkSetIpv4Address {
-    pub fn new(name: &str, ip_address: Ipv4Addr) -> Result<Self, NetworkError> {
+fn sockaddr_in(address: Ipv4Addr) -> nix::libc::sockaddr {
+    let addr = nix::libc::sockaddr_in {
+        sin_family: nix::libc::AF_INET as nix::libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: nix::libc::in_addr {
+            s_addr: u32::from_ne_bytes(address.octets()),
+        },
+        sin_zero: [0; 8],
+    };
+
+    // sockaddr_in and sockaddr are both 16 bytes, sockaddr is just the generic view of it
+    unsafe { std::mem::transmute::<nix::libc::sockaddr_in, nix::libc::sockaddr>(addr) }
+}
+
+pub struct NetworkRouteIpv4 {
+    entry: nix::libc::rtentry,
+    _device: Option<CString>,
+}
+
+impl NetworkRouteIpv4 {
+    pub fn new(
+        destination: Ipv4Addr,
+        prefix_len: u8,
+        gateway: Option<Ipv4Addr>,
+        metric: u32,
+        device: Option<&str>,
+    ) -> Result<Self, NetworkError> {
+        if prefix_len > 32 {
+            return Err(NetworkError::InvalidRoute(format!(
+                "Ipv4 prefix length is invalid (got '{prefix_len}')"
+            )));
+        }
+        // The kernel stores `rt_metric - 1`, so 0 is reserved for "use the default"
+        let metric = metric
+            .checked_add(1)
+            .and_then(|v| i16::try_from(v).ok())
+            .ok_or_else(|| {
+                NetworkError::InvalidRoute(format!(
+                    "Ipv4 route metric is too large for the ioctl interface (got '{metric}')"
+                ))
+            })?;
+
+        let mask = prefix_to_mask(prefix_len);
+        let destination = Ipv4Addr::from(u32::from(destination) & mask);
+
+        let mut entry: nix::libc::rtentry = unsafe { std::mem::zeroed() };
+        entry.rt_dst = sockaddr_in(destination);
+        entry.rt_genmask = sockaddr_in(Ipv4Addr::from(mask));
+        entry.rt_metric = metric;
+        entry.rt_flags = nix::libc::RTF_UP;
+        if prefix_len == 32 {
+            entry.rt_flags |= nix::libc::RTF_HOST;
+        }
+        if let Some(gateway) = gateway {
+            entry.rt_gateway = sockaddr_in(gateway);
+            entry.rt_flags |= nix::libc::RTF_GATEWAY;
+        }
+
+        let device = device
+            .map(|name| validate_name(name).map(|_| CString::new(name).unwrap()))
+            .transpose()?;
+        if let Some(device) = &device {
+            entry.rt_dev = device.as_ptr() as *mut _;
+        }
+
         Ok(Self {
-            network_name: validate_name(name)?,
-            address_family: 0x0002,
-            _0: [0; 2],
-            ip_address,
-            _1: [0; 16],
+            entry,
+            _device: device,
         })
     }
+
+    pub fn as_ptr(&self) -> *const nix::libc::rtentry {
+        &self.entry
+    }
+}
+
+pub struct NetworkRouteIpv6 {
+    entry: nix::libc::in6_rtmsg,
 }
 
-#[derive(Debug)]
+/// The fields of `struct in6_rtmsg`, which libc keeps private.
 #[repr(C)]
-pub struct NetworkSetSubnetMask {
-    network_name: [u8; 16],
-    address_family: u16,
-    _0: [u8; 2],
-    subnet_mask: [u8; 4],
-    _1: [u8; 16],
+struct In6Rtmsg {
+    destination: [u8; 16],
+    source: [u8; 16],
+    gateway: [u8; 16],
+    route_type: u32,
+    destination_len: u16,
+    source_len: u16,
+    metric: u32,
+    info: nix::libc::c_ulong,
+    flags: u32,
+    ifindex: nix::libc::c_int,
 }
 
-impl NetworkSetSubnetMask {
-    pub fn new(name: &str, subnet_mask: u8) -> Result<Self, NetworkError> {
-        let subnet_mask = subnet_mask as u32;
-        let subnet_mask_cleared: u32 = 0xFFFF_FFFF >> (32 - subnet_mask) << (32 - subnet_mask);
+const _: () = assert!(
+    std::mem::size_of::<In6Rtmsg>() == std::mem::size_of::<nix::libc::in6_rtmsg>()
+        && std::mem::align_of::<In6Rtmsg>() == std::mem::align_of::<nix::libc::in6_rtmsg>()
+);
+
+impl NetworkRouteIpv6 {
+    pub fn new(
+        destination: Ipv6Addr,
+        prefix_len: u8,
+        gateway: Option<Ipv6Addr>,
+        metric: u32,
+        ifindex: u32,
+    ) -> Result<Self, NetworkError> {
+        if prefix_len > 128 {
+            return Err(NetworkError::InvalidRoute(format!(
+                "Ipv6 prefix length is invalid (got '{prefix_len}')"
+            )));
+        }
+
+        let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+        let destination = Ipv6Addr::from(u128::from(destination) & mask);
+
+        let mut flags = nix::libc::RTF_UP as u32;
+        if prefix_len == 128 {
+            flags |= nix::libc::RTF_HOST as u32;
+        }
+        if gateway.is_some() {
+            flags |= nix::libc::RTF_GATEWAY as u32;
+        }
+
+        let entry = In6Rtmsg {
+            destination: destination.octets(),
+            source: [0; 16],
+            gateway: gateway.unwrap_or(Ipv6Addr::UNSPECIFIED).octets(),
+            route_type: 0,
+            destination_len: prefix_len as u16,
+            source_len: 0,
+            metric,
+            info: 0,
+            flags,
+            ifindex: ifindex as nix::libc::c_int,
+        };
+
         Ok(Self {
-            network_name: validate_name(name)?,
-            address_family: 0x0002,
-            _0: [0; 2],
-            subnet_mask: subnet_mask_cleared.to_be_bytes(),
-            _1: [0; 16],
+            entry: unsafe { std::mem::transmute::<In6Rtmsg, nix::libc::in6_rtmsg>(entry) },
         })
     }
+
+    pub fn as_ptr(&self) -> *const nix::libc::in6_rtmsg {
+        &self.entry
+    }
 }
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct NetworkSetFlags {
-    network_name: [u8; 16],
-    flags: u16,
-    _0: [u8; 22],
+pub struct NetworkArpEntry {
+    entry: nix::libc::arpreq,
 }
 
-impl NetworkSetFlags {
-    pub fn blank(name: &str) -> Result<Self, NetworkError> {
-        Ok(Self {
-            network_name: validate_name(name)?,
-            flags: 0,
-            _0: [0; 22],
-        })
+impl NetworkArpEntry {
+    pub fn new(name: &str, address: Ipv4Addr, mac: MacAddr) -> Result<Self, NetworkError> {
+        let mut entry: nix::libc::arpreq = unsafe { std::mem::zeroed() };
+        entry.arp_pa = sockaddr_in(address);
+        entry.arp_ha.sa_family = nix::libc::ARPHRD_ETHER;
+        for (data, octet) in entry.arp_ha.sa_data.iter_mut().zip(mac.octets()) {
+            *data = octet as nix::libc::c_char;
+        }
+        entry.arp_flags = consts::ATF_PERM | consts::ATF_COM;
+        for (dev, c) in entry.arp_dev.iter_mut().zip(validate_name(name)?) {
+            *dev = c as nix::libc::c_char;
+        }
+
+        Ok(Self { entry })
+    }
+
+    pub fn blank(name: &str, address: Ipv4Addr) -> Result<Self, NetworkError> {
+        Self::new(name, address, MacAddr::default())
+    }
+
+    /// The hardware address, if the entry has been resolved.
+    pub fn address(&self) -> Option<MacAddr> {
+        if self.entry.arp_flags & consts::ATF_COM == 0 {
+            return None;
+        }
+        let mut octets = [0; 6];
+        for (octet, data) in octets.iter_mut().zip(self.entry.arp_ha.sa_data) {
+            *octet = data as u8;
+        }
+        Some(MacAddr::new(octets))
     }
 
-    pub fn set_flag(&mut self, flag: u16) {
-        self.flags |= flag;
+    /// For `SIOCGARP`, which writes the entry it finds back into the request.
+    pub fn as_mut_ptr(&mut self) -> *mut nix::libc::arpreq {
+        &mut self.entry
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_to_mask_covers_every_length() {
+        assert_eq!(prefix_to_mask(0), 0);
+        assert_eq!(prefix_to_mask(1), 0x8000_0000);
+        assert_eq!(prefix_to_mask(24), 0xFFFF_FF00);
+        assert_eq!(prefix_to_mask(31), 0xFFFF_FFFE);
+        assert_eq!(prefix_to_mask(32), u32::MAX);
+        for prefix_len in 0..=32 {
+            assert_eq!(prefix_to_mask(prefix_len).count_ones(), prefix_len as u32);
+            assert_eq!(prefix_to_mask(prefix_len).leading_ones(), prefix_len as u32);
+        }
+    }
+
+    #[test]
+    fn names_must_fit_ifr_name() {
+        assert_eq!(&validate_name("tun0").unwrap()[..5], b"tun0\0");
+        assert!(validate_name("fifteen-chars-x").is_ok());
+        assert!(validate_name("sixteen-chars-xx").is_err());
+        assert!(validate_name("tün0").is_err());
+    }
+
+    #[test]
+    fn ifreq_fields_round_trip() {
+        let mut ifreq = IfReq::new("tun0").unwrap();
+        assert_eq!(ifreq.name(), "tun0");
+        assert_eq!(IfReq::unnamed().name(), "");
+
+        ifreq.set_flags(0x1043);
+        assert_eq!(ifreq.flags(), 0x1043);
+        ifreq.set_value(9000);
+        assert_eq!(ifreq.value(), 9000);
+        ifreq.set_ipv4(Ipv4Addr::new(10, 1, 2, 3));
+        assert_eq!(ifreq.ipv4(), Ipv4Addr::new(10, 1, 2, 3));
 
-    pub fn clear_flag(&mut self, flag: u16) {
-        self.flags &= !flag;
+        let address = MacAddr::from_node_id(7);
+        ifreq.set_hardware_address(nix::libc::ARPHRD_ETHER, address);
+        assert_eq!(ifreq.hardware_address(), address);
     }
 }
@@ -0,0 +1,73 @@
+use super::{
+    buf::PacketBuf, error::NetworkError, packet::IpPacket, pool::PooledPacket, NetworkTun,
+};
+
+/// The reading half of a [`NetworkTun`], created by [`NetworkTun::split`].
+///
+/// Dereferences to the tun so the interface can still be configured from this half.
+pub struct TunReader {
+    tun: NetworkTun,
+}
+
+impl TunReader {
+    pub(crate) fn new(tun: NetworkTun) -> Self {
+        Self { tun }
+    }
+
+    pub fn recv(&mut self) -> Result<IpPacket, NetworkError> {
+        self.tun.recv()
+    }
+
+    pub fn recv_pooled(&mut self) -> Result<PooledPacket, NetworkError> {
+        self.tun.recv_pooled()
+    }
+
+    pub fn recv_segments(&mut self) -> Result<Vec<IpPacket>, NetworkError> {
+        self.tun.recv_segments()
+    }
+
+    pub fn recv_batch(&mut self, bufs: &mut [PacketBuf]) -> Result<usize, NetworkError> {
+        self.tun.recv_batch(bufs)
+    }
+}
+
+impl std::ops::Deref for TunReader {
+    type Target = NetworkTun;
+
+    fn deref(&self) -> &NetworkTun {
+        &self.tun
+    }
+}
+
+/// The writing half of a [`NetworkTun`], created by [`NetworkTun::split`].
+///
+/// Dereferences to the tun so the interface can still be configured from this half.
+pub struct TunWriter {
+    tun: NetworkTun,
+}
+
+impl TunWriter {
+    pub(crate) fn new(tun: NetworkTun) -> Self {
+        Self { tun }
+    }
+
+    pub fn send(&mut self, packet: &IpPacket) -> Result<(), NetworkError> {
+        self.tun.send(packet)
+    }
+
+    pub fn send_pooled(&mut self, packet: &PooledPacket) -> Result<(), NetworkError> {
+        self.tun.send_pooled(packet)
+    }
+
+    pub fn send_batch(&mut self, packets: &[PacketBuf]) -> Result<usize, NetworkError> {
+        self.tun.send_batch(packets)
+    }
+}
+
+impl std::ops::Deref for TunWriter {
+    type Target = NetworkTun;
+
+    fn deref(&self) -> &NetworkTun {
+        &self.tun
+    }
+}
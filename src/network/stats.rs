@@ -0,0 +1,162 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::error::NetworkError;
+
+const NET_DEV: &str = "/proc/thread-self/net/dev";
+
+/// Counters maintained by the kernel for an interface.
+///
+/// From the host's point of view `tx` is traffic routed into the interface (and so read by us),
+/// while `rx` is traffic we wrote to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+impl InterfaceStats {
+    /// Reads the counters for the named interface from `/proc/thread-self/net/dev`, which shows
+    /// the network namespace of the calling thread.
+    pub fn read(name: &str) -> Result<Self, NetworkError> {
+        let dev = std::fs::read_to_string(NET_DEV)
+            .map_err(NetworkError::on(&format!("reading {NET_DEV}"), name))?;
+        Self::parse(&dev, name)
+    }
+
+    /// Finds the named interface's counters in the contents of a `net/dev` table.
+    fn parse(dev: &str, name: &str) -> Result<Self, NetworkError> {
+        // Each line is the name followed by 8 receive counters and 8 transmit counters
+        let line = dev
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(interface, _)| interface.trim() == name)
+            .map(|(_, counters)| counters)
+            .ok_or_else(|| {
+                NetworkError::InvalidResponse(format!("No counters in {NET_DEV} for '{name}'"))
+            })?;
+        let counters = line
+            .split_whitespace()
+            .map(str::parse::<u64>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|counters| counters.len() >= 16)
+            .ok_or_else(|| {
+                NetworkError::InvalidResponse(format!(
+                    "Could not parse the counters in {NET_DEV} for '{name}' (got '{}')",
+                    line.trim()
+                ))
+            })?;
+
+        Ok(Self {
+            rx_bytes: counters[0],
+            rx_packets: counters[1],
+            rx_errors: counters[2],
+            rx_dropped: counters[3],
+            tx_bytes: counters[8],
+            tx_packets: counters[9],
+            tx_errors: counters[10],
+            tx_dropped: counters[11],
+        })
+    }
+}
+
+/// Counters kept in userspace by [`NetworkTun`](super::NetworkTun).
+#[derive(Debug, Default)]
+pub(crate) struct TunCounters {
+    pub packets_received: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub parse_errors: AtomicU64,
+}
+
+impl TunCounters {
+    pub fn record_received(&self, bytes: usize) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_parse_error(&self) {
+        self.parse_errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Kernel interface counters combined with the counters recorded by `recv`.
+///
+/// Packets counted in `interface.tx_packets` but not in `packets_received` are still queued
+/// in the kernel or were dropped before we read them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TunStats {
+    pub interface: InterfaceStats,
+    /// Frames read from the tun. Like the kernel's `tx_packets`, a GSO frame counts once, even
+    /// though [`NetworkTun::recv_segments`](super::NetworkTun::recv_segments) splits it into
+    /// several packets.
+    pub packets_received: u64,
+    /// Bytes read from the tun, without the packet information or virtio-net headers.
+    pub bytes_received: u64,
+    pub parse_errors: u64,
+}
+
+impl TunStats {
+    pub(crate) fn new(interface: InterfaceStats, counters: &TunCounters) -> Self {
+        Self {
+            interface,
+            packets_received: counters.packets_received.load(Ordering::Relaxed),
+            bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+            parse_errors: counters.parse_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  119163     1203    0    0    0     0          0         0   119163    1203    0    0    0     0       0          0
+  tun0:     840       10    1    2    0     0          0         0     4200       50    3    4    0     0       0          0
+tun10: 18446744073709551615 1 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+";
+
+    #[test]
+    fn counters_are_taken_from_the_named_line() {
+        assert_eq!(
+            InterfaceStats::parse(DEV, "tun0").unwrap(),
+            InterfaceStats {
+                rx_bytes: 840,
+                rx_packets: 10,
+                rx_errors: 1,
+                rx_dropped: 2,
+                tx_bytes: 4200,
+                tx_packets: 50,
+                tx_errors: 3,
+                tx_dropped: 4,
+            }
+        );
+
+        // Long names push the counters up against the colon
+        let stats = InterfaceStats::parse(DEV, "tun10").unwrap();
+        assert_eq!((stats.rx_bytes, stats.rx_packets), (u64::MAX, 1));
+    }
+
+    #[test]
+    fn missing_and_malformed_lines_are_errors() {
+        for name in ["tun1", "face", ""] {
+            assert!(matches!(
+                InterfaceStats::parse(DEV, name),
+                Err(NetworkError::InvalidResponse(_))
+            ));
+        }
+        assert!(InterfaceStats::parse("tun0: 1 2 3", "tun0").is_err());
+        assert!(
+            InterfaceStats::parse("tun0: 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 x", "tun0").is_err()
+        );
+    }
+}
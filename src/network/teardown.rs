@@ -0,0 +1,373 @@
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use super::{
+    backend::NetworkBackend, error::NetworkError, flags::InterfaceFlags, mac::MacAddr,
+    netns::NetNamespace, routes::Route,
+};
+
+/// A change made to an interface which can be undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Change {
+    Address(IpAddr, u8),
+    Route(Route),
+    /// The MTU before it was first changed.
+    Mtu(u32),
+    /// The tx queue length before it was first changed.
+    TxQueueLen(u32),
+    /// The hardware address before it was first changed.
+    HardwareAddress(MacAddr),
+    Neighbor(IpAddr),
+    /// A link-layer multicast filter entry.
+    MulticastAddress(MacAddr),
+    /// The flags before they were first changed.
+    Flags(InterfaceFlags),
+    /// The alias before it was first changed.
+    Alias(String),
+}
+
+/// Every change made through a tun and its clones, undone in reverse order once the last handle
+/// is dropped.
+pub(crate) struct Teardown {
+    name: Mutex<String>,
+    backend: Arc<dyn NetworkBackend>,
+    namespace: Option<NetNamespace>,
+    changes: Mutex<Vec<Change>>,
+    enabled: AtomicBool,
+}
+
+impl Teardown {
+    pub(crate) fn new(
+        name: &str,
+        backend: Arc<dyn NetworkBackend>,
+        namespace: Option<NetNamespace>,
+        enabled: bool,
+    ) -> Self {
+        Self {
+            name: Mutex::new(name.to_owned()),
+            backend,
+            namespace,
+            changes: Mutex::new(vec![]),
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Change>> {
+        self.changes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Follows the interface to its new name, so changes are undone under the right name.
+    ///
+    /// Recorded routes name their device too, so they're moved over to the new name as well.
+    pub(crate) fn rename(&self, new_name: &str) {
+        *self.name.lock().unwrap_or_else(|e| e.into_inner()) = new_name.to_owned();
+        for change in self.lock().iter_mut() {
+            if let Change::Route(route) = change {
+                route.device = Some(new_name.to_owned());
+            }
+        }
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record(&self, change: Change) {
+        self.lock().push(change);
+    }
+
+    /// Records the original MTU or flags, which only matters the first time they change.
+    pub(crate) fn record_original(&self, change: Change) {
+        let mut changes = self.lock();
+        let recorded = changes
+            .iter()
+            .any(|x| std::mem::discriminant(x) == std::mem::discriminant(&change));
+        if !recorded {
+            changes.push(change);
+        }
+    }
+
+    /// Forgets a change which was undone by hand.
+    pub(crate) fn forget(&self, change: &Change) {
+        let mut changes = self.lock();
+        if let Some(position) = changes.iter().rposition(|x| x == change) {
+            changes.remove(position);
+        }
+    }
+
+    /// Undoes every recorded change, newest first, returning the first error encountered.
+    ///
+    /// Failures don't stop the remaining changes from being undone.
+    pub(crate) fn undo(&self) -> Result<(), NetworkError> {
+        let changes = std::mem::take(&mut *self.lock());
+        if !self.enabled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        match &self.namespace {
+            Some(namespace) => namespace.enter(|| self.undo_here(changes)),
+            None => self.undo_here(changes),
+        }
+    }
+
+    fn undo_here(&self, changes: Vec<Change>) -> Result<(), NetworkError> {
+        let name = self.name.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let name = name.as_str();
+        let mut result = Ok(());
+        for change in changes.into_iter().rev() {
+            let undone = match change {
+                Change::Address(address, prefix_len) => {
+                    self.backend.delete_address(name, address, prefix_len)
+                }
+                // The kernel drops a device's routes when it goes down, as it must to be renamed
+                Change::Route(route) => match super::routes::delete_route(&route) {
+                    Err(e) if e.errno() == Some(nix::errno::Errno::ESRCH) => Ok(()),
+                    undone => undone,
+                },
+                Change::Mtu(mtu) => self.backend.set_mtu(name, mtu),
+                Change::TxQueueLen(len) => self.backend.set_tx_queue_len(name, len),
+                Change::HardwareAddress(address) => {
+                    self.backend.set_hardware_address(name, address)
+                }
+                Change::Neighbor(address) => self.backend.delete_neighbor(name, address),
+                Change::MulticastAddress(address) => {
+                    super::multicast::delete_link_multicast(name, address)
+                }
+                Change::Flags(flags) => self.backend.set_flags(name, flags),
+                Change::Alias(alias) => self.backend.set_alias(name, &alias),
+            };
+            if result.is_ok() {
+                result = undone;
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for Teardown {
+    fn drop(&mut self) {
+        let _ = self.undo();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    /// Logs every undo call, failing MTU changes to [`Recorder::FAILING`]. Teardown never reads
+    /// or adds anything, so those calls are unsupported.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        const FAILING: u32 = 9999;
+
+        fn log(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    fn unsupported<T>(operation: &str) -> Result<T, NetworkError> {
+        Err(NetworkError::Unsupported(format!(
+            "{operation} isn't used by teardown"
+        )))
+    }
+
+    impl NetworkBackend for Recorder {
+        fn flags(&self, _: &str) -> Result<InterfaceFlags, NetworkError> {
+            unsupported("flags")
+        }
+
+        fn set_flags(&self, name: &str, flags: InterfaceFlags) -> Result<(), NetworkError> {
+            self.log(format!("{name} flags {:#x}", flags.bits()));
+            Ok(())
+        }
+
+        fn add_address(&self, _: &str, _: IpAddr, _: u8) -> Result<(), NetworkError> {
+            unsupported("add_address")
+        }
+
+        fn add_address_default_mask(&self, _: &str, _: Ipv4Addr) -> Result<u8, NetworkError> {
+            unsupported("add_address_default_mask")
+        }
+
+        fn delete_address(
+            &self,
+            name: &str,
+            address: IpAddr,
+            prefix_len: u8,
+        ) -> Result<(), NetworkError> {
+            self.log(format!("{name} delete {address}/{prefix_len}"));
+            Ok(())
+        }
+
+        fn mtu(&self, _: &str) -> Result<u32, NetworkError> {
+            unsupported("mtu")
+        }
+
+        fn set_mtu(&self, name: &str, mtu: u32) -> Result<(), NetworkError> {
+            self.log(format!("{name} mtu {mtu}"));
+            if mtu == Self::FAILING {
+                return Err(NetworkError::Unsupported("mtu".to_string()));
+            }
+            Ok(())
+        }
+
+        fn tx_queue_len(&self, _: &str) -> Result<u32, NetworkError> {
+            unsupported("tx_queue_len")
+        }
+
+        fn set_tx_queue_len(&self, name: &str, len: u32) -> Result<(), NetworkError> {
+            self.log(format!("{name} txqueuelen {len}"));
+            Ok(())
+        }
+
+        fn hardware_address(&self, _: &str) -> Result<MacAddr, NetworkError> {
+            unsupported("hardware_address")
+        }
+
+        fn set_hardware_address(&self, name: &str, address: MacAddr) -> Result<(), NetworkError> {
+            self.log(format!("{name} address {address}"));
+            Ok(())
+        }
+
+        fn rename(&self, _: &str, _: &str) -> Result<(), NetworkError> {
+            unsupported("rename")
+        }
+
+        fn alias(&self, _: &str) -> Result<String, NetworkError> {
+            unsupported("alias")
+        }
+
+        fn set_alias(&self, name: &str, alias: &str) -> Result<(), NetworkError> {
+            self.log(format!("{name} alias '{alias}'"));
+            Ok(())
+        }
+
+        fn add_neighbor(&self, _: &str, _: IpAddr, _: MacAddr) -> Result<(), NetworkError> {
+            unsupported("add_neighbor")
+        }
+
+        fn neighbor(&self, _: &str, _: IpAddr) -> Result<Option<MacAddr>, NetworkError> {
+            unsupported("neighbor")
+        }
+
+        fn delete_neighbor(&self, name: &str, address: IpAddr) -> Result<(), NetworkError> {
+            self.log(format!("{name} delete neighbor {address}"));
+            Ok(())
+        }
+    }
+
+    fn recorded(enabled: bool) -> (Arc<Recorder>, Teardown) {
+        let recorder = Arc::new(Recorder::default());
+        let teardown = Teardown::new("tun0", recorder.clone(), None, enabled);
+        (recorder, teardown)
+    }
+
+    #[test]
+    fn changes_are_undone_newest_first() {
+        let (recorder, teardown) = recorded(true);
+        teardown.record(Change::Address(Ipv4Addr::new(10, 0, 0, 1).into(), 24));
+        teardown.record_original(Change::Mtu(1500));
+        teardown.record(Change::Neighbor(Ipv6Addr::LOCALHOST.into()));
+        teardown.record_original(Change::Alias(String::new()));
+        teardown.record_original(Change::Flags(InterfaceFlags::empty()));
+
+        teardown.undo().unwrap();
+        assert_eq!(
+            recorder.calls(),
+            [
+                "tun0 flags 0x0",
+                "tun0 alias ''",
+                "tun0 delete neighbor ::1",
+                "tun0 mtu 1500",
+                "tun0 delete 10.0.0.1/24",
+            ]
+        );
+
+        // Everything was taken, so undoing again does nothing
+        teardown.undo().unwrap();
+        assert_eq!(recorder.calls().len(), 5);
+    }
+
+    #[test]
+    fn only_the_first_original_is_kept() {
+        let (recorder, teardown) = recorded(true);
+        teardown.record_original(Change::Mtu(1500));
+        teardown.record_original(Change::TxQueueLen(500));
+        teardown.record_original(Change::Mtu(9000));
+        teardown.record_original(Change::TxQueueLen(1000));
+
+        teardown.undo().unwrap();
+        assert_eq!(recorder.calls(), ["tun0 txqueuelen 500", "tun0 mtu 1500"]);
+    }
+
+    #[test]
+    fn forgetting_removes_the_newest_match() {
+        let (recorder, teardown) = recorded(true);
+        let address = Change::Address(Ipv4Addr::new(10, 0, 0, 1).into(), 24);
+        teardown.record(address.clone());
+        teardown.record_original(Change::Mtu(1500));
+        teardown.record(address.clone());
+        teardown.forget(&address);
+        teardown.forget(&Change::Neighbor(Ipv6Addr::LOCALHOST.into()));
+
+        teardown.undo().unwrap();
+        assert_eq!(
+            recorder.calls(),
+            ["tun0 mtu 1500", "tun0 delete 10.0.0.1/24"]
+        );
+    }
+
+    #[test]
+    fn failures_dont_stop_the_rest() {
+        let (recorder, teardown) = recorded(true);
+        teardown.record_original(Change::TxQueueLen(500));
+        teardown.record_original(Change::Mtu(Recorder::FAILING));
+        teardown.record_original(Change::HardwareAddress(MacAddr::from_node_id(1)));
+
+        assert!(matches!(teardown.undo(), Err(NetworkError::Unsupported(_))));
+        assert_eq!(
+            recorder.calls(),
+            [
+                "tun0 address 02:00:00:00:00:01",
+                "tun0 mtu 9999",
+                "tun0 txqueuelen 500",
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_are_followed_and_disabled_teardowns_do_nothing() {
+        let (recorder, teardown) = recorded(true);
+        let route = Route::new(Ipv4Addr::new(10, 1, 0, 0).into(), 16);
+        teardown.record(Change::Route(route.clone().device("tun0")));
+        teardown.record_original(Change::Mtu(1500));
+        teardown.rename("radio0");
+
+        // Routes deleted by hand after the rename name the new device, and must still match
+        teardown.forget(&Change::Route(route.device("radio0")));
+        teardown.undo().unwrap();
+        assert_eq!(recorder.calls(), ["radio0 mtu 1500"]);
+
+        let (recorder, teardown) = recorded(false);
+        teardown.record_original(Change::Mtu(1500));
+        teardown.undo().unwrap();
+        drop(teardown);
+        assert!(recorder.calls().is_empty());
+    }
+}
@@ -1,22 +1,132 @@
 use std::{
-    io::Read,
-    net::Ipv4Addr,
-    os::fd::{AsRawFd as _, OwnedFd},
+    collections::VecDeque,
+    io::{IoSlice, IoSliceMut, Read, Write},
+    net::{IpAddr, Ipv4Addr},
+    os::fd::{AsFd as _, AsRawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
-use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
 
+#[cfg(feature = "netlink")]
+use super::monitor::LinkMonitor;
 use super::{
+    backend::{IoctlBackend, NetworkBackend},
+    buf::PacketBuf,
     consts,
     error::NetworkError,
+    features::TunFeatures,
+    filter::BpfProgram,
+    flags::InterfaceFlags,
+    interfaces::{self, InterfaceInfo},
+    mac::MacAddr,
+    multicast::{add_link_multicast, delete_link_multicast, MulticastGroup},
+    netns::NetNamespace,
     packet::IpPacket,
-    settings::{NetworkCreateTunTap, NetworkSetFlags, NetworkSetIpv4Address, NetworkSetSubnetMask},
+    pool::{PacketPool, PooledPacket},
+    preflight::Preflight,
+    routes::{self, Route},
+    settings::{validate_name, IfReq},
+    split::{TunReader, TunWriter},
+    stats::{InterfaceStats, TunCounters, TunStats},
+    teardown::{Change, Teardown},
+    vnet::{TunOffloads, VirtioNetHeader},
 };
 
+/// Large enough for the packet information header, a virtio-net header and a 64k GSO packet.
+const VNET_BUF_SIZE: usize = 4 + VirtioNetHeader::SIZE + 65535;
+
+/// Room for an Ethernet header and a VLAN tag on top of the MTU on tap devices.
+const ETH_FRAME_OVERHEAD: usize = 18;
+
+/// Whether tun reads honour `RWF_NOWAIT`, cleared the first time the kernel says they don't.
+static NOWAIT_READS: AtomicBool = AtomicBool::new(true);
+
+/// The protocol for the packet information header, taken from the Ip version or, on a tap
+/// device, the frame's ethertype.
+pub(crate) fn frame_proto(data: &[u8], tap: bool) -> Result<u16, NetworkError> {
+    if tap {
+        return match data.get(12..14) {
+            Some(ethertype) => Ok(u16::from_be_bytes([ethertype[0], ethertype[1]])),
+            None => Err(NetworkError::PacketParseError(
+                "provided bytes are too short to be an Ethernet frame (<14 bytes)".to_string(),
+            )),
+        };
+    }
+
+    match data.first().map(|x| x >> 4) {
+        Some(4) => Ok(consts::ETH_P_IP),
+        Some(6) => Ok(consts::ETH_P_IPV6),
+        _ => Err(NetworkError::PacketParseError(
+            "Could not determine Ip version".to_string(),
+        )),
+    }
+}
+
 pub struct NetworkTunBuilder {
     name: Option<String>,
     ip_address: Option<Ipv4Addr>,
     subnet_mask: Option<u8>,
+    addresses: Vec<(IpAddr, u8)>,
+    backend: Option<Arc<dyn NetworkBackend>>,
+    offloads: Option<TunOffloads>,
+    pool: Option<PacketPool>,
+    teardown: bool,
+    namespace: Option<NetNamespace>,
+    tap: bool,
+    carrier: bool,
+    tx_queue_len: Option<u32>,
+    send_buffer: Option<u32>,
+    mac_address: Option<MacAddr>,
+    exclusive: bool,
+    napi: bool,
+    napi_frags: bool,
+}
+
+/// Issues a tun ioctl which doesn't take an ifreq. `arg` is usually a pointer, but requests such as
+/// `TUNSETOFFLOAD` take their value directly.
+fn tun_ioctl<T>(
+    tun_fd: &impl AsRawFd,
+    (request, operation): (u64, &str),
+    arg: *const T,
+    name: &str,
+) -> Result<(), NetworkError> {
+    let code = unsafe { nix::libc::ioctl(tun_fd.as_raw_fd(), request, arg) };
+    nix::errno::Errno::result(code).map_err(NetworkError::on(operation, name))?;
+    Ok(())
+}
+
+fn negotiate_offloads(
+    tun_fd: &std::fs::File,
+    name: &str,
+    offloads: TunOffloads,
+) -> Result<TunOffloads, NetworkError> {
+    let header_size = VirtioNetHeader::SIZE as nix::libc::c_int;
+    tun_ioctl(
+        tun_fd,
+        (consts::TUNSETVNETHDRSZ, "TUNSETVNETHDRSZ"),
+        &header_size,
+        name,
+    )?;
+
+    let mut offloads = offloads;
+    loop {
+        let arg = std::ptr::without_provenance::<()>(offloads.bits() as usize);
+        match tun_ioctl(tun_fd, (consts::TUNSETOFFLOAD, "TUNSETOFFLOAD"), arg, name) {
+            Ok(()) => return Ok(offloads),
+            // USO is only supported from Linux 6.2, so fall back to the older offloads
+            Err(e)
+                if e.errno() == Some(nix::errno::Errno::EINVAL)
+                    && offloads.intersects(TunOffloads::USO4 | TunOffloads::USO6) =>
+            {
+                offloads.remove(TunOffloads::USO4 | TunOffloads::USO6);
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 impl NetworkTunBuilder {
@@ -35,55 +145,220 @@ impl NetworkTunBuilder {
         self
     }
 
+    /// Adds an extra address to the interface. Ipv6 and multiple addresses require a backend
+    /// which supports them, such as the netlink backend.
+    pub fn address(&mut self, address: IpAddr, prefix_len: u8) -> &mut Self {
+        self.addresses.push((address, prefix_len));
+        self
+    }
+
+    /// Selects the backend used to configure the interface, defaulting to [`IoctlBackend`].
+    pub fn backend<B: NetworkBackend + 'static>(&mut self, backend: B) -> &mut Self {
+        let _ = self.backend.insert(Arc::new(backend));
+        self
+    }
+
+    /// Creates a tap device, which carries Ethernet frames instead of Ip packets.
+    ///
+    /// Frames are read and written whole, so use the raw packet APIs such as
+    /// [`NetworkTun::recv_pooled`] and [`NetworkTun::send_batch`] rather than `recv` and `send`.
+    pub fn tap(&mut self) -> &mut Self {
+        self.tap = true;
+        self
+    }
+
+    /// Whether the device starts with carrier, defaulting to `true`. Without carrier the host
+    /// treats the link as down until [`NetworkTun::set_carrier`] is called.
+    pub fn carrier(&mut self, carrier: bool) -> &mut Self {
+        self.carrier = carrier;
+        self
+    }
+
+    /// Fails if an interface with the requested name already exists, rather than attaching to a
+    /// persistent device which another process may own. The error's errno is then `EBUSY`.
+    pub fn exclusive(&mut self) -> &mut Self {
+        self.exclusive = true;
+        self
+    }
+
+    /// Hands written packets to the kernel through NAPI, so they are processed in batches
+    /// rather than one at a time.
+    pub fn napi(&mut self) -> &mut Self {
+        self.napi = true;
+        self
+    }
+
+    /// Like [`NetworkTunBuilder::napi`], but with each written frame built from page fragments
+    /// so it can be merged by GRO. Only supported on tap devices, and requires `CAP_NET_ADMIN`.
+    pub fn napi_frags(&mut self) -> &mut Self {
+        self.napi = true;
+        self.napi_frags = true;
+        self
+    }
+
+    /// Sets the Ethernet address of a tap device, which otherwise gets a random one.
+    pub fn mac_address(&mut self, address: MacAddr) -> &mut Self {
+        let _ = self.mac_address.insert(address);
+        self
+    }
+
+    /// Sets how many packets the kernel queues for us before dropping, see
+    /// [`NetworkTun::set_tx_queue_len`].
+    pub fn tx_queue_len(&mut self, len: u32) -> &mut Self {
+        let _ = self.tx_queue_len.insert(len);
+        self
+    }
+
+    /// Sets how many bytes we can have written but not yet processed by the kernel, see
+    /// [`NetworkTun::set_send_buffer`].
+    pub fn send_buffer(&mut self, size: u32) -> &mut Self {
+        let _ = self.send_buffer.insert(size);
+        self
+    }
+
+    /// Enables virtio-net headers and requests `offloads` from the kernel.
+    ///
+    /// GSO super-packets are segmented in userspace, so `recv` still yields MTU sized packets.
+    /// Offloads the kernel doesn't support are dropped, see [`NetworkTun::offloads`].
+    pub fn vnet_hdr(&mut self, offloads: TunOffloads) -> &mut Self {
+        let _ = self.offloads.insert(offloads);
+        self
+    }
+
+    /// Sets the pool `recv` reads into, defaulting to one sized to the interface's MTU.
+    pub fn pool(&mut self, pool: PacketPool) -> &mut Self {
+        let _ = self.pool.insert(pool);
+        self
+    }
+
+    /// Whether changes made to the interface are undone when the tun is dropped, defaulting to
+    /// `true`. See [`NetworkTun::close`].
+    pub fn teardown_on_drop(&mut self, teardown: bool) -> &mut Self {
+        self.teardown = teardown;
+        self
+    }
+
+    /// Creates the interface inside `namespace` rather than the current one.
+    ///
+    /// A custom backend must have been created inside the namespace too, see
+    /// [`NetNamespace::enter`].
+    pub fn namespace(&mut self, namespace: NetNamespace) -> &mut Self {
+        let _ = self.namespace.insert(namespace);
+        self
+    }
+
+    /// Creates and configures the tun. Permission errors explain what is missing, see
+    /// [`Preflight`].
     pub fn build(&self) -> Result<NetworkTun, NetworkError> {
+        // Explained from inside the namespace, as that's the one which has to be checked
+        let build = || self.build_here().map_err(Preflight::explain);
+        match &self.namespace {
+            Some(namespace) => namespace.enter(build),
+            None => build(),
+        }
+    }
+
+    fn build_here(&self) -> Result<NetworkTun, NetworkError> {
         let tun_fd = std::fs::OpenOptions::new()
             .read(true)
             .write(true)
             .create(false)
             .open("/dev/net/tun")
-            .map_err(|e| NetworkError::IoError(format!("IO error on tun. {e:?}")))?;
+            .map_err(NetworkError::call("opening /dev/net/tun"))?;
+
+        if self.tap && self.offloads.is_some() {
+            return Err(NetworkError::Unsupported(
+                "virtio-net headers are not supported on tap devices".to_string(),
+            ));
+        }
+        if self.napi_frags && !self.tap {
+            return Err(NetworkError::Unsupported(
+                "NAPI fragments are only supported on tap devices".to_string(),
+            ));
+        }
+
+        // Unknown flags are ignored by the kernel, so check for them before creating anything
+        let mut wanted = match self.tap {
+            true => TunFeatures::TAP,
+            false => TunFeatures::TUN,
+        };
+        if self.offloads.is_some() {
+            wanted |= TunFeatures::VNET_HDR;
+        }
+        if !self.carrier {
+            wanted |= TunFeatures::NO_CARRIER;
+        }
+        if self.napi {
+            wanted |= TunFeatures::NAPI;
+        }
+        if self.napi_frags {
+            wanted |= TunFeatures::NAPI_FRAGS;
+        }
+        TunFeatures::from_device(&tun_fd)?.require(wanted)?;
 
         let tun_name = {
-            let ifreq = match &self.name {
-                Some(name) => NetworkCreateTunTap::new_tun(name.as_str()),
-                None => NetworkCreateTunTap::new_tun_no_name(),
-            }?;
-
-            let code = unsafe {
-                nix::libc::ioctl(
-                    tun_fd.as_raw_fd(),
-                    consts::TUNSETIFF,
-                    &ifreq as *const NetworkCreateTunTap,
-                )
+            let mut ifreq = match &self.name {
+                Some(name) => IfReq::new(name.as_str())?,
+                None => IfReq::unnamed(),
             };
-            nix::errno::Errno::result(code).map_err(NetworkError::IoctlError)?;
-            ifreq.get_name()
+            // Features are named after the TUNSETIFF flags which request them. Exclusive creation
+            // isn't reported as a feature, so it can't be checked for beforehand
+            let mut flags = wanted.bits();
+            if self.exclusive {
+                flags |= consts::IFF_TUN_EXCL;
+            }
+            ifreq.set_flags(flags);
+
+            match ifreq.ioctl(&tun_fd, (consts::TUNSETIFF, "TUNSETIFF")) {
+                // Keeps the errno, but says why exclusive creation is busy
+                Err(e) if self.exclusive && e.errno() == Some(nix::errno::Errno::EBUSY) => {
+                    return Err(NetworkError::os(
+                        "exclusive TUNSETIFF (the interface already exists)",
+                        Some(ifreq.name().as_str()),
+                        nix::errno::Errno::EBUSY,
+                    ));
+                }
+                result => result?,
+            };
+            ifreq.name()
         };
 
-        let sock_fd = socket(
-            AddressFamily::Inet,
-            SockType::Datagram,
-            SockFlag::empty(),
-            None,
-        )
-        .map_err(NetworkError::IoctlError)?;
+        let offloads = self
+            .offloads
+            .map(|offloads| negotiate_offloads(&tun_fd, tun_name.as_str(), offloads))
+            .transpose()?;
+
+        let backend = match &self.backend {
+            Some(backend) => backend.clone(),
+            None => Arc::new(IoctlBackend::new()?),
+        };
+
+        let pool = match &self.pool {
+            Some(pool) => pool.clone(),
+            None => PacketPool::new(frame_size(backend.mtu(tun_name.as_str())?, self.tap)),
+        };
 
         let new_tun = NetworkTun {
+            teardown: Arc::new(Teardown::new(
+                tun_name.as_str(),
+                backend.clone(),
+                self.namespace.clone(),
+                self.teardown,
+            )),
             tun_fd,
-            sock_fd,
+            backend,
             tun_name,
+            counters: Arc::new(TunCounters::default()),
+            offloads,
+            pending: VecDeque::new(),
+            vnet_buf: vec![],
+            pool: Arc::new(Mutex::new(pool)),
+            namespace: self.namespace.clone(),
+            tap: self.tap,
         };
 
-        if let Some(ip_address) = self.ip_address {
-            let addr = NetworkSetIpv4Address::new(new_tun.tun_name.as_str(), ip_address)?;
-            let code = unsafe {
-                nix::libc::ioctl(
-                    new_tun.sock_fd.as_raw_fd(),
-                    consts::SIOCSIFADDR,
-                    &addr as *const NetworkSetIpv4Address,
-                )
-            };
-            nix::errno::Errno::result(code).map_err(NetworkError::IoctlError)?;
+        if let Some(address) = self.mac_address {
+            new_tun.set_mac_address(address)?;
         }
 
         if self.ip_address.is_none() && self.subnet_mask.is_some() {
@@ -92,21 +367,25 @@ impl NetworkTunBuilder {
             ));
         }
 
-        if let Some(mask) = self.subnet_mask {
-            if mask == 0 || mask > 32 {
-                return Err(NetworkError::InvalidSubnetMask(format!(
-                    "Subnet mask value is invalid (got '{mask}')"
-                )));
+        match (self.ip_address, self.subnet_mask) {
+            (Some(ip_address), Some(prefix_len)) => {
+                new_tun.add_address(IpAddr::V4(ip_address), prefix_len)?
             }
-            let mask = NetworkSetSubnetMask::new(new_tun.tun_name.as_str(), mask)?;
-            let code = unsafe {
-                nix::libc::ioctl(
-                    new_tun.sock_fd.as_raw_fd(),
-                    consts::SIOCSIFNETMASK,
-                    &mask as *const NetworkSetSubnetMask,
-                )
-            };
-            nix::errno::Errno::result(code).map_err(NetworkError::IoctlError)?;
+            // Without a mask the kernel picks one, as it always has for these builders
+            (Some(ip_address), None) => new_tun.add_address_default_mask(ip_address)?,
+            _ => {}
+        }
+
+        for &(address, prefix_len) in &self.addresses {
+            new_tun.add_address(address, prefix_len)?;
+        }
+
+        if let Some(len) = self.tx_queue_len {
+            new_tun.set_tx_queue_len(len)?;
+        }
+
+        if let Some(size) = self.send_buffer {
+            new_tun.set_send_buffer(size)?;
         }
 
         Ok(new_tun)
@@ -114,9 +393,28 @@ impl NetworkTunBuilder {
 }
 
 pub struct NetworkTun {
+    // Dropped first, so changes are undone while the interface still exists
+    teardown: Arc<Teardown>,
     pub(crate) tun_fd: std::fs::File,
-    pub(crate) sock_fd: OwnedFd,
+    pub(crate) backend: Arc<dyn NetworkBackend>,
     tun_name: String,
+    pub(crate) counters: Arc<TunCounters>,
+    offloads: Option<TunOffloads>,
+    /// Segments of a GSO frame which didn't fit in the caller's buffers.
+    pending: VecDeque<PooledPacket>,
+    /// Where frames with virtio-net headers are read before being segmented.
+    vnet_buf: Vec<u8>,
+    pool: Arc<Mutex<PacketPool>>,
+    namespace: Option<NetNamespace>,
+    tap: bool,
+}
+
+/// The largest frame read from a device with the given MTU.
+fn frame_size(mtu: u32, tap: bool) -> usize {
+    match tap {
+        true => mtu as usize + ETH_FRAME_OVERHEAD,
+        false => mtu as usize,
+    }
 }
 
 impl NetworkTun {
@@ -125,56 +423,803 @@ impl NetworkTun {
             name: None,
             ip_address: None,
             subnet_mask: None,
+            addresses: vec![],
+            backend: None,
+            offloads: None,
+            pool: None,
+            teardown: true,
+            namespace: None,
+            tap: false,
+            carrier: true,
+            tx_queue_len: None,
+            send_buffer: None,
+            mac_address: None,
+            exclusive: false,
+            napi: false,
+            napi_frags: false,
         }
     }
 
-    fn get_flags(&self) -> Result<NetworkSetFlags, NetworkError> {
-        let flags = NetworkSetFlags::blank(self.tun_name.as_str())?;
+    /// Opens another handle to the same tun, sharing its configuration, counters and pool.
+    ///
+    /// Each packet is only delivered to one of the handles reading from the tun.
+    pub fn try_clone(&self) -> Result<NetworkTun, NetworkError> {
+        let tun_fd = self
+            .tun_fd
+            .try_clone()
+            .map_err(NetworkError::on("dup", &self.tun_name))?;
 
-        let code = unsafe {
-            nix::libc::ioctl(
-                self.sock_fd.as_raw_fd(),
-                consts::SIOCGIFFLAGS,
-                &flags as *const NetworkSetFlags,
-            )
-        };
-        nix::errno::Errno::result(code).map_err(NetworkError::IoctlError)?;
+        Ok(NetworkTun {
+            teardown: self.teardown.clone(),
+            tun_fd,
+            backend: self.backend.clone(),
+            tun_name: self.tun_name.clone(),
+            counters: self.counters.clone(),
+            offloads: self.offloads,
+            pending: VecDeque::new(),
+            vnet_buf: vec![],
+            pool: self.pool.clone(),
+            namespace: self.namespace.clone(),
+            tap: self.tap,
+        })
+    }
+
+    /// Splits the tun into halves which can be moved to separate threads, one for reading and
+    /// one for writing. Both halves can still configure the interface.
+    pub fn split(self) -> Result<(TunReader, TunWriter), NetworkError> {
+        let writer = self.try_clone()?;
+        Ok((TunReader::new(self), TunWriter::new(writer)))
+    }
+
+    /// Whether changes made through this tun and its clones are undone once the last of them is
+    /// dropped.
+    pub fn set_teardown_on_drop(&self, teardown: bool) {
+        self.teardown.set_enabled(teardown);
+    }
+
+    /// Undoes every address, route, MTU and flag change made through this tun and its clones,
+    /// then closes it.
+    ///
+    /// Unlike dropping the tun this reports failures, and it undoes changes even if clones are
+    /// still open or teardown on drop is disabled.
+    pub fn close(self) -> Result<(), NetworkError> {
+        self.teardown.set_enabled(true);
+        self.teardown.undo()
+    }
+
+    pub fn name(&self) -> &str {
+        self.tun_name.as_str()
+    }
+
+    /// Whether this is a tap device carrying Ethernet frames.
+    pub fn is_tap(&self) -> bool {
+        self.tap
+    }
 
-        Ok(flags)
+    /// The namespace the interface was created in, if not the current one.
+    pub fn namespace(&self) -> Option<&NetNamespace> {
+        self.namespace.as_ref()
     }
 
-    fn set_flags(&self, flags: &NetworkSetFlags) -> Result<(), NetworkError> {
-        let code = unsafe {
-            nix::libc::ioctl(
-                self.sock_fd.as_raw_fd(),
-                consts::SIOCSIFFLAGS,
-                flags as *const NetworkSetFlags,
-            )
+    /// Runs `f` inside the interface's namespace.
+    fn configure<T, F>(&self, f: F) -> Result<T, NetworkError>
+    where
+        F: FnOnce() -> Result<T, NetworkError>,
+    {
+        let result = match &self.namespace {
+            Some(namespace) => namespace.enter(f),
+            None => f(),
         };
-        nix::errno::Errno::result(code).map_err(NetworkError::IoctlError)?;
+        result.map_err(|e| e.with_interface(&self.tun_name))
+    }
 
-        Ok(())
+    pub fn info(&self) -> Result<InterfaceInfo, NetworkError> {
+        self.configure(|| interfaces::find_interface(self.tun_name.as_str()))?
+            .ok_or_else(|| {
+                NetworkError::os(
+                    "finding interface",
+                    Some(&self.tun_name),
+                    nix::errno::Errno::ENODEV,
+                )
+            })
+    }
+
+    /// Subscribes to link and address changes on this interface.
+    #[cfg(feature = "netlink")]
+    pub fn monitor(&self) -> Result<LinkMonitor, NetworkError> {
+        self.configure(|| {
+            let mut monitor = LinkMonitor::new()?;
+            monitor.watch(self.tun_name.as_str())?;
+            Ok(monitor)
+        })
+    }
+
+    /// Reads the current interface flags, including the carrier (`LOWER_UP`) state.
+    pub fn flags(&self) -> Result<InterfaceFlags, NetworkError> {
+        self.configure(|| self.backend.flags(self.tun_name.as_str()))
+    }
+
+    /// Applies `update` to the current flags and writes them back, returning the new flags.
+    ///
+    /// Changes to read-only flags such as `RUNNING` or `LOWER_UP` are ignored.
+    pub fn update_flags<F>(&self, update: F) -> Result<InterfaceFlags, NetworkError>
+    where
+        F: FnOnce(&mut InterfaceFlags),
+    {
+        let mut flags = self.flags()?;
+        self.teardown.record_original(Change::Flags(flags));
+        update(&mut flags);
+        self.configure(|| self.backend.set_flags(self.tun_name.as_str(), flags))?;
+
+        self.flags()
+    }
+
+    /// Whether the device has carrier, which is what the host sees as the link state.
+    pub fn carrier(&self) -> Result<bool, NetworkError> {
+        Ok(self.flags()?.contains(InterfaceFlags::LOWER_UP))
+    }
+
+    /// Raises or drops carrier, so the host sees the link going up or down without the interface
+    /// itself being taken down.
+    pub fn set_carrier(&self, carrier: bool) -> Result<(), NetworkError> {
+        let carrier = carrier as nix::libc::c_int;
+        tun_ioctl(
+            &self.tun_fd,
+            (consts::TUNSETCARRIER, "TUNSETCARRIER"),
+            &carrier,
+            &self.tun_name,
+        )
     }
 
     pub fn set_up(&self, up: bool) -> Result<(), NetworkError> {
-        let mut flags = self.get_flags()?;
+        self.update_flags(|flags| flags.set(InterfaceFlags::UP, up))?;
+        Ok(())
+    }
+
+    /// Marks the interface as multicast capable, which both tuns and taps are when created.
+    pub fn set_multicast(&self, multicast: bool) -> Result<(), NetworkError> {
+        self.update_flags(|flags| flags.set(InterfaceFlags::MULTICAST, multicast))?;
+        Ok(())
+    }
+
+    /// Accepts frames sent to the multicast `address` on a tap device, on top of those the host
+    /// already listens for.
+    pub fn add_multicast_address(&self, address: MacAddr) -> Result<(), NetworkError> {
+        self.require_tap("Link-layer multicast entries")?;
+        self.configure(|| add_link_multicast(self.tun_name.as_str(), address))?;
+        // Adding an address twice leaves a single entry, which teardown should delete only once
+        self.teardown.forget(&Change::MulticastAddress(address));
+        self.teardown.record(Change::MulticastAddress(address));
+        Ok(())
+    }
+
+    pub fn delete_multicast_address(&self, address: MacAddr) -> Result<(), NetworkError> {
+        self.require_tap("Link-layer multicast entries")?;
+        self.configure(|| delete_link_multicast(self.tun_name.as_str(), address))?;
+        self.teardown.forget(&Change::MulticastAddress(address));
+        Ok(())
+    }
+
+    /// Adds a permanent entry to a tap device's neighbor table, so `address` resolves to `mac`
+    /// without an ARP or neighbor discovery round trip. Replaces any existing entry.
+    ///
+    /// Ipv6 entries need the netlink backend.
+    pub fn add_neighbor(&self, address: IpAddr, mac: MacAddr) -> Result<(), NetworkError> {
+        self.require_tap("Neighbor entries")?;
+        self.configure(|| {
+            self.backend
+                .add_neighbor(self.tun_name.as_str(), address, mac)
+        })?;
+        // Replacing an entry shouldn't make teardown delete it twice
+        self.teardown.forget(&Change::Neighbor(address));
+        self.teardown.record(Change::Neighbor(address));
+        Ok(())
+    }
+
+    /// The hardware address `address` currently resolves to, whether added by hand or learned.
+    pub fn neighbor(&self, address: IpAddr) -> Result<Option<MacAddr>, NetworkError> {
+        self.require_tap("Neighbor entries")?;
+        self.configure(|| self.backend.neighbor(self.tun_name.as_str(), address))
+    }
+
+    pub fn delete_neighbor(&self, address: IpAddr) -> Result<(), NetworkError> {
+        self.require_tap("Neighbor entries")?;
+        self.configure(|| {
+            self.backend
+                .delete_neighbor(self.tun_name.as_str(), address)
+        })?;
+        self.teardown.forget(&Change::Neighbor(address));
+        Ok(())
+    }
+
+    /// Joins the Ipv4 or Ipv6 multicast `group` on this interface until the returned membership
+    /// is dropped. Renaming the interface afterwards doesn't affect it.
+    pub fn join_multicast(&self, group: IpAddr) -> Result<MulticastGroup, NetworkError> {
+        self.configure(|| MulticastGroup::join(group, self.tun_name.as_str()))
+    }
+
+    pub fn add_address(&self, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+        self.configure(|| {
+            self.backend
+                .add_address(self.tun_name.as_str(), address, prefix_len)
+        })?;
+        self.teardown.record(Change::Address(address, prefix_len));
+        Ok(())
+    }
+
+    /// Adds `address` with the mask the kernel picks for it.
+    fn add_address_default_mask(&self, address: Ipv4Addr) -> Result<(), NetworkError> {
+        let prefix_len = self.configure(|| {
+            self.backend
+                .add_address_default_mask(self.tun_name.as_str(), address)
+        })?;
+        self.teardown
+            .record(Change::Address(IpAddr::V4(address), prefix_len));
+        Ok(())
+    }
+
+    pub fn delete_address(&self, address: IpAddr, prefix_len: u8) -> Result<(), NetworkError> {
+        self.configure(|| {
+            self.backend
+                .delete_address(self.tun_name.as_str(), address, prefix_len)
+        })?;
+        self.teardown.forget(&Change::Address(address, prefix_len));
+        Ok(())
+    }
+
+    pub fn mtu(&self) -> Result<u32, NetworkError> {
+        self.configure(|| self.backend.mtu(self.tun_name.as_str()))
+    }
+
+    /// Sets the MTU, switching to a larger pool if packets would no longer fit in its buffers.
+    pub fn set_mtu(&self, mtu: u32) -> Result<(), NetworkError> {
+        self.teardown.record_original(Change::Mtu(self.mtu()?));
+        self.configure(|| self.backend.set_mtu(self.tun_name.as_str(), mtu))?;
+
+        let frame_size = frame_size(mtu, self.tap);
+        let mut pool = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+        if pool.mtu() < frame_size {
+            *pool = pool.resized(frame_size);
+        }
+        Ok(())
+    }
+
+    /// Renames the interface, which has to be down.
+    ///
+    /// Handles created with [`NetworkTun::try_clone`] or [`NetworkTun::split`] keep the old name,
+    /// so rename before cloning. The rename is not undone on drop.
+    pub fn rename(&mut self, new_name: &str) -> Result<(), NetworkError> {
+        validate_name(new_name)?;
+        if new_name.is_empty() {
+            return Err(NetworkError::InvalidName(
+                "Network name cannot be empty".to_string(),
+            ));
+        }
+        if self.flags()?.contains(InterfaceFlags::UP) {
+            return Err(NetworkError::Unsupported(format!(
+                "Interface '{}' must be down to be renamed",
+                self.tun_name
+            )));
+        }
+
+        self.configure(|| self.backend.rename(self.tun_name.as_str(), new_name))?;
+        self.tun_name = new_name.to_owned();
+        self.teardown.rename(new_name);
+        Ok(())
+    }
+
+    /// The interface's description, empty if none is set.
+    pub fn alias(&self) -> Result<String, NetworkError> {
+        self.configure(|| self.backend.alias(self.tun_name.as_str()))
+    }
+
+    /// Sets a description shown alongside the interface, such as the radio channel it carries.
+    /// An empty alias clears it.
+    pub fn set_alias(&self, alias: &str) -> Result<(), NetworkError> {
+        self.teardown.record_original(Change::Alias(self.alias()?));
+        self.configure(|| self.backend.set_alias(self.tun_name.as_str(), alias))
+    }
+
+    pub fn mac_address(&self) -> Result<MacAddr, NetworkError> {
+        self.require_tap("MAC addresses")?;
+        self.configure(|| self.backend.hardware_address(self.tun_name.as_str()))
+    }
 
-        if up {
-            flags.set_flag(consts::IFF_UP);
-        } else {
-            flags.clear_flag(consts::IFF_UP);
+    pub fn set_mac_address(&self, address: MacAddr) -> Result<(), NetworkError> {
+        self.require_tap("MAC addresses")?;
+        if address.is_multicast() {
+            return Err(NetworkError::InvalidMacAddress(format!(
+                "A device's address cannot be multicast (got '{address}')"
+            )));
         }
 
-        self.set_flags(&flags)
+        self.teardown
+            .record_original(Change::HardwareAddress(self.mac_address()?));
+        self.configure(|| {
+            self.backend
+                .set_hardware_address(self.tun_name.as_str(), address)
+        })
+    }
+
+    pub fn tx_queue_len(&self) -> Result<u32, NetworkError> {
+        self.configure(|| self.backend.tx_queue_len(self.tun_name.as_str()))
+    }
+
+    /// Sets how many packets the kernel queues for us before dropping them.
+    ///
+    /// The default of 500 packets can hold seconds of traffic on a slow link, so a short queue
+    /// keeps latency down at the cost of dropping bursts.
+    pub fn set_tx_queue_len(&self, len: u32) -> Result<(), NetworkError> {
+        self.teardown
+            .record_original(Change::TxQueueLen(self.tx_queue_len()?));
+        self.configure(|| self.backend.set_tx_queue_len(self.tun_name.as_str(), len))
+    }
+
+    pub fn send_buffer(&self) -> Result<u32, NetworkError> {
+        let mut size: nix::libc::c_int = 0;
+        tun_ioctl(
+            &self.tun_fd,
+            (consts::TUNGETSNDBUF, "TUNGETSNDBUF"),
+            &mut size as *mut nix::libc::c_int,
+            &self.tun_name,
+        )?;
+
+        Ok(size as u32)
+    }
+
+    /// Sets how many bytes we can have written to the tun but not yet processed by the kernel,
+    /// after which writes block.
+    pub fn set_send_buffer(&self, size: u32) -> Result<(), NetworkError> {
+        let size = nix::libc::c_int::try_from(size).map_err(|_| {
+            NetworkError::Unsupported(format!(
+                "Send buffer size is too large (got '{size}', max: {})",
+                nix::libc::c_int::MAX
+            ))
+        })?;
+        tun_ioctl(
+            &self.tun_fd,
+            (consts::TUNSETSNDBUF, "TUNSETSNDBUF"),
+            &size,
+            &self.tun_name,
+        )
+    }
+
+    /// Installs `route` through this interface, overriding any device set on the route.
+    pub fn add_route(&self, route: Route) -> Result<Route, NetworkError> {
+        let route = route.device(self.tun_name.as_str());
+        self.configure(|| routes::add_route(&route))?;
+        self.teardown.record(Change::Route(route.clone()));
+        Ok(route)
+    }
+
+    pub fn delete_route(&self, route: Route) -> Result<(), NetworkError> {
+        let route = route.device(self.tun_name.as_str());
+        self.configure(|| routes::delete_route(&route))?;
+        self.teardown.forget(&Change::Route(route));
+        Ok(())
+    }
+
+    /// Lists the routes currently going through this interface.
+    pub fn routes(&self) -> Result<Vec<Route>, NetworkError> {
+        Ok(self
+            .configure(routes::list_routes)?
+            .into_iter()
+            .filter(|route| route.device.as_deref() == Some(self.tun_name.as_str()))
+            .collect())
+    }
+
+    /// Reads the kernel's interface counters along with the counters recorded by `recv`.
+    pub fn stats(&self) -> Result<TunStats, NetworkError> {
+        let interface = self.configure(|| InterfaceStats::read(self.tun_name.as_str()))?;
+        Ok(TunStats::new(interface, &self.counters))
+    }
+
+    /// The offloads negotiated with the kernel, or `None` if virtio-net headers are disabled.
+    pub fn offloads(&self) -> Option<TunOffloads> {
+        self.offloads
+    }
+
+    fn require_tap(&self, what: &str) -> Result<(), NetworkError> {
+        match self.tap {
+            true => Ok(()),
+            false => Err(NetworkError::Unsupported(format!(
+                "{what} only apply to tap devices"
+            ))),
+        }
+    }
+
+    /// Attaches `program` to a tap device, replacing any current filter. Frames it rejects are
+    /// dropped before they reach us.
+    pub fn attach_filter(&self, program: &BpfProgram) -> Result<(), NetworkError> {
+        self.require_tap("BPF filters")?;
+        let instructions = program.instructions();
+        let fprog = nix::libc::sock_fprog {
+            len: instructions.len() as u16,
+            filter: instructions.as_ptr() as *mut nix::libc::sock_filter,
+        };
+
+        tun_ioctl(
+            &self.tun_fd,
+            (consts::TUNATTACHFILTER, "TUNATTACHFILTER"),
+            &fprog,
+            &self.tun_name,
+        )
+    }
+
+    pub fn detach_filter(&self) -> Result<(), NetworkError> {
+        self.require_tap("BPF filters")?;
+        let fprog = nix::libc::sock_fprog {
+            len: 0,
+            filter: std::ptr::null_mut(),
+        };
+
+        tun_ioctl(
+            &self.tun_fd,
+            (consts::TUNDETACHFILTER, "TUNDETACHFILTER"),
+            &fprog,
+            &self.tun_name,
+        )
+    }
+
+    /// Only lets frames addressed to `addresses` reach a tap device, along with every multicast
+    /// and broadcast frame if `all_multicast` is set. An empty list removes the filter.
+    ///
+    /// Multicast addresses can be listed individually. The kernel only matches 8 unicast
+    /// addresses, and silently disables the filter when given more.
+    pub fn set_mac_filter(
+        &self,
+        addresses: &[MacAddr],
+        all_multicast: bool,
+    ) -> Result<(), NetworkError> {
+        self.require_tap("MAC filters")?;
+        let count = u16::try_from(addresses.len()).map_err(|_| {
+            NetworkError::Unsupported(format!(
+                "Too many addresses for a MAC filter (got '{}')",
+                addresses.len()
+            ))
+        })?;
+
+        // struct tun_filter, followed by the addresses
+        let flags = match all_multicast {
+            true => consts::TUN_FLT_ALLMULTI,
+            false => 0,
+        };
+        let mut filter = vec![];
+        filter.extend_from_slice(&flags.to_ne_bytes());
+        filter.extend_from_slice(&count.to_ne_bytes());
+        filter.extend(addresses.iter().flat_map(MacAddr::octets));
+
+        tun_ioctl(
+            &self.tun_fd,
+            (consts::TUNSETTXFILTER, "TUNSETTXFILTER"),
+            filter.as_ptr(),
+            &self.tun_name,
+        )
+    }
+
+    /// The pool received packets are taken from.
+    pub fn pool(&self) -> PacketPool {
+        self.pool.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Whether a frame can be read without blocking.
+    ///
+    /// Polled rather than toggling `O_NONBLOCK`, which would also affect clones of this tun.
+    fn readable(&self) -> Result<bool, NetworkError> {
+        let mut fds = [PollFd::new(self.tun_fd.as_fd(), PollFlags::POLLIN)];
+        let n =
+            poll(&mut fds, PollTimeout::ZERO).map_err(NetworkError::on("poll", &self.tun_name))?;
+        Ok(n > 0)
+    }
+
+    /// Reads one frame into `bufs`, returning `None` if the tun is empty and either `wait` is
+    /// unset or the tun is non-blocking.
+    ///
+    /// Reads without waiting use `RWF_NOWAIT`, which unlike `O_NONBLOCK` doesn't affect clones of
+    /// this tun, and saves polling before every read.
+    fn read_vectored(
+        &mut self,
+        bufs: &mut [IoSliceMut],
+        wait: bool,
+    ) -> Result<Option<usize>, NetworkError> {
+        if !wait {
+            if NOWAIT_READS.load(Ordering::Relaxed) {
+                // IoSliceMut is guaranteed to be ABI compatible with iovec
+                let n = unsafe {
+                    nix::libc::preadv2(
+                        self.tun_fd.as_raw_fd(),
+                        bufs.as_ptr() as *const nix::libc::iovec,
+                        bufs.len() as nix::libc::c_int,
+                        -1,
+                        nix::libc::RWF_NOWAIT,
+                    )
+                };
+                match nix::errno::Errno::result(n) {
+                    Ok(n) => return Ok(Some(n as usize)),
+                    Err(nix::errno::Errno::EAGAIN) => return Ok(None),
+                    // Older kernels don't support it on tuns, so poll before reading instead
+                    Err(nix::errno::Errno::EOPNOTSUPP) => {
+                        NOWAIT_READS.store(false, Ordering::Relaxed)
+                    }
+                    Err(e) => return Err(NetworkError::os("preadv2", Some(&self.tun_name), e)),
+                }
+            }
+            if !self.readable()? {
+                return Ok(None);
+            }
+        }
+
+        match self.tun_fd.read_vectored(bufs) {
+            Ok(n) => Ok(Some(n)),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(NetworkError::os("read", Some(&self.tun_name), e)),
+        }
+    }
+
+    /// Reads a frame with a virtio-net header and splits it into segments from the pool, queued
+    /// on `pending`. Returns whether a frame was read.
+    fn read_segments(&mut self, wait: bool) -> Result<bool, NetworkError> {
+        // Kept between reads, rather than allocating and zeroing 64k for every frame
+        let mut buf = std::mem::take(&mut self.vnet_buf);
+        buf.resize(VNET_BUF_SIZE, 0);
+        let result = self.segment_frame(&mut buf, wait);
+        self.vnet_buf = buf;
+        result
+    }
+
+    fn segment_frame(&mut self, buf: &mut [u8], wait: bool) -> Result<bool, NetworkError> {
+        let mut pi = [0u8; 4];
+        let Some(n) =
+            self.read_vectored(&mut [IoSliceMut::new(&mut pi), IoSliceMut::new(buf)], wait)?
+        else {
+            return Ok(false);
+        };
+        let data = &buf[..n.saturating_sub(pi.len())];
+
+        self.counters
+            .record_received(data.len().saturating_sub(VirtioNetHeader::SIZE));
+
+        let pool = self.pool();
+        VirtioNetHeader::from_bytes(data)
+            .and_then(|header| {
+                header.segment_into(
+                    &data[VirtioNetHeader::SIZE..],
+                    &mut self.pending,
+                    |headers, payload| {
+                        let mut segment = pool.get();
+                        segment.fill(headers)?;
+                        segment.push_back(payload)?;
+                        Ok(segment)
+                    },
+                )
+            })
+            .map(|_| true)
+            .inspect_err(|_| self.counters.record_parse_error())
+    }
+
+    /// Reads one frame into `bufs`, returning how many were filled. With virtio-net headers one
+    /// frame may fill several buffers, and any segments which don't fit are kept for later.
+    fn read_into(
+        &mut self,
+        bufs: &mut [PacketBuf],
+        wait: bool,
+    ) -> Result<Option<usize>, NetworkError> {
+        if self.offloads.is_some() {
+            if !self.read_segments(wait)? {
+                return Ok(None);
+            }
+            return self.drain_pending(bufs).map(Some);
+        }
+
+        let Some(buf) = bufs.first_mut() else {
+            return Ok(Some(0));
+        };
+
+        buf.clear();
+        let Some(n) = self.read_frame(buf.spare_mut(), wait)? else {
+            return Ok(None);
+        };
+        buf.set_len(n);
+        Ok(Some(1))
+    }
+
+    /// Reads one frame without a virtio-net header into `buf`, stripping the packet information.
+    fn read_frame(&mut self, buf: &mut [u8], wait: bool) -> Result<Option<usize>, NetworkError> {
+        let mut pi = [0u8; 4];
+        let Some(n) =
+            self.read_vectored(&mut [IoSliceMut::new(&mut pi), IoSliceMut::new(buf)], wait)?
+        else {
+            return Ok(None);
+        };
+
+        let flags = u16::from_ne_bytes([pi[0], pi[1]]);
+        if flags & consts::TUN_PKT_STRIP != 0 {
+            self.counters.record_parse_error();
+            return Err(NetworkError::PacketParseError(format!(
+                "packet was truncated to fit the buffer (capacity: {})",
+                buf.len()
+            )));
+        }
+
+        let n = n.saturating_sub(pi.len());
+        self.counters.record_received(n);
+        Ok(Some(n))
+    }
+
+    /// Moves leftover segments from a previous GSO frame into `bufs`.
+    fn drain_pending(&mut self, bufs: &mut [PacketBuf]) -> Result<usize, NetworkError> {
+        let mut count = 0;
+        for buf in bufs.iter_mut() {
+            let Some(segment) = self.pending.pop_front() else {
+                break;
+            };
+            buf.fill(&segment)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Reads a single frame, splitting GSO super-packets into MTU sized packets when virtio-net
+    /// headers are enabled.
+    pub fn recv_segments(&mut self) -> Result<Vec<IpPacket>, NetworkError> {
+        if self.offloads.is_none() {
+            return self.recv().map(|packet| vec![packet]);
+        }
+
+        if self.pending.is_empty() {
+            self.read_segments(true)?;
+        }
+
+        self.pending
+            .drain(..)
+            .map(|segment| segment.parse())
+            .collect::<Result<_, _>>()
+            .inspect_err(|_| self.counters.record_parse_error())
+    }
+
+    /// Reads the next packet into a buffer from the pool, without parsing it.
+    pub fn recv_pooled(&mut self) -> Result<PooledPacket, NetworkError> {
+        if self.offloads.is_some() {
+            loop {
+                if let Some(segment) = self.pending.pop_front() {
+                    return Ok(segment);
+                }
+                self.read_segments(true)?;
+            }
+        }
+
+        let mut packet = self.pool().get();
+        loop {
+            if let Some(n) = self.read_frame(packet.spare_mut(), true)? {
+                packet.set_len(n);
+                return Ok(packet);
+            }
+        }
     }
 
     pub fn recv(&mut self) -> Result<IpPacket, NetworkError> {
-        let mut buf = [0u8; 2000];
-        let n = self.tun_fd.read(&mut buf).expect("");
+        let packet = self.recv_pooled()?;
+        packet
+            .parse()
+            .inspect_err(|_| self.counters.record_parse_error())
+    }
+
+    /// Fills `bufs` with as many packets as are available, returning how many were filled.
+    ///
+    /// Blocks until at least one packet arrives, then drains the tun without blocking, taking
+    /// one read per frame. Packets are not parsed, so parse failures are not counted in
+    /// [`NetworkTun::stats`].
+    pub fn recv_batch(&mut self, bufs: &mut [PacketBuf]) -> Result<usize, NetworkError> {
+        let mut count = self.drain_pending(bufs)?;
+
+        while count == 0 && !bufs.is_empty() {
+            count += self.read_into(bufs, true)?.unwrap_or_default();
+        }
+
+        while count < bufs.len() {
+            match self.read_into(&mut bufs[count..], false) {
+                Ok(Some(n)) => count += n,
+                // Packets already read are more useful to the caller than the error
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Writes a raw packet, prepending the packet information and virtio-net headers.
+    fn write_packet(&mut self, data: &[u8]) -> Result<(), NetworkError> {
+        let proto = frame_proto(data, self.tap)?;
+
+        let mut pi = [0u8; 4];
+        pi[2..4].copy_from_slice(&proto.to_be_bytes());
+        let vnet = VirtioNetHeader::default().to_bytes();
+
+        let mut slices = vec![IoSlice::new(&pi)];
+        if self.offloads.is_some() {
+            slices.push(IoSlice::new(&vnet));
+        }
+        slices.push(IoSlice::new(data));
+
+        let expected = slices.iter().map(|slice| slice.len()).sum::<usize>();
+        let n = self
+            .tun_fd
+            .write_vectored(&slices)
+            .map_err(NetworkError::on("write", &self.tun_name))?;
+        if n != expected {
+            return Err(NetworkError::os(
+                "write",
+                Some(&self.tun_name),
+                std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    format!("short write (wrote: {n}, expected: {expected})"),
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn send(&mut self, packet: &IpPacket) -> Result<(), NetworkError> {
+        self.write_packet(&packet.to_bytes())
+    }
+
+    pub fn send_pooled(&mut self, packet: &PooledPacket) -> Result<(), NetworkError> {
+        self.write_packet(packet.as_slice())
+    }
+
+    /// Writes every packet in `packets`, returning how many were written.
+    ///
+    /// Tuns take one frame per write, so this makes a `writev` per packet and saves no syscalls
+    /// over calling [`NetworkTun::send`] in a loop. Stops at the first failure, which is only
+    /// returned if nothing could be written.
+    pub fn send_batch(&mut self, packets: &[PacketBuf]) -> Result<usize, NetworkError> {
+        for (i, packet) in packets.iter().enumerate() {
+            if let Err(e) = self.write_packet(packet.as_slice()) {
+                return if i == 0 { Err(e) } else { Ok(i) };
+            }
+        }
+
+        Ok(packets.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tun_frames_take_their_proto_from_the_ip_version() {
+        assert_eq!(
+            frame_proto(&[0x45, 0, 0, 20], false).unwrap(),
+            consts::ETH_P_IP
+        );
+        assert_eq!(
+            frame_proto(&[0x60, 0, 0, 0], false).unwrap(),
+            consts::ETH_P_IPV6
+        );
+        for invalid in [&[][..], &[0x00], &[0x50, 0, 0, 0], &[0xFF]] {
+            assert!(matches!(
+                frame_proto(invalid, false),
+                Err(NetworkError::PacketParseError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn tap_frames_take_their_proto_from_the_ethertype() {
+        let mut frame = [0u8; 14];
+        frame[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+        assert_eq!(frame_proto(&frame, true).unwrap(), 0x0806);
 
-        let _flags = ((buf[0] as u16) << 8) | (buf[1] as u16);
-        let _proto = ((buf[2] as u16) << 8) | (buf[3] as u16);
+        // The IP version nibble means nothing in front of a MAC header
+        frame[0] = 0x45;
+        frame[12..14].copy_from_slice(&consts::ETH_P_IPV6.to_be_bytes());
+        assert_eq!(frame_proto(&frame, true).unwrap(), consts::ETH_P_IPV6);
 
-        IpPacket::from_bytes(&buf[4..n])
+        assert!(matches!(
+            frame_proto(&frame[..13], true),
+            Err(NetworkError::PacketParseError(_))
+        ));
     }
 }
@@ -0,0 +1,355 @@
+use std::{collections::VecDeque, os::fd::AsRawFd as _};
+
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+
+use super::{
+    buf::PacketBuf, consts, error::NetworkError, packet::IpPacket, pool::PooledPacket,
+    tun::frame_proto, NetworkTun,
+};
+
+const WRITE_TAG: u64 = 1 << 32;
+const CANCEL_TAG: u64 = 1 << 33;
+const PI_LEN: usize = 4;
+
+/// A tun driven through io_uring with a pool of registered buffers.
+///
+/// Every read buffer always has a read outstanding, so packets are already waiting in userspace
+/// memory when `recv` is called. Writes are submitted without waiting for them to complete.
+/// Configuration is still done through the wrapped [`NetworkTun`].
+pub struct UringTun {
+    // The ring must be dropped before the buffers it references
+    ring: IoUring,
+    buffers: Vec<Box<[u8]>>,
+    depth: usize,
+    completed: VecDeque<(usize, usize)>,
+    /// Which read buffers the kernel may currently be writing into.
+    reading: Vec<bool>,
+    free_writes: Vec<usize>,
+    tun: NetworkTun,
+}
+
+impl UringTun {
+    /// Takes over `tun`'s I/O with `depth` outstanding reads and up to `depth` in-flight writes,
+    /// each using a buffer of `buf_size` bytes.
+    pub fn new(tun: NetworkTun, depth: usize, buf_size: usize) -> Result<Self, NetworkError> {
+        if tun.offloads().is_some() {
+            return Err(NetworkError::Unsupported(
+                "virtio-net headers are not supported with io_uring".to_string(),
+            ));
+        }
+        if depth == 0 || depth > u16::MAX as usize / 2 {
+            return Err(NetworkError::Unsupported(format!(
+                "io_uring depth must be between 1 and {} (got '{depth}')",
+                u16::MAX / 2
+            )));
+        }
+
+        let ring =
+            IoUring::new((depth * 2) as u32).map_err(NetworkError::call("io_uring_setup"))?;
+
+        let mut buffers = (0..depth * 2)
+            .map(|_| vec![0u8; PI_LEN + buf_size].into_boxed_slice())
+            .collect::<Vec<_>>();
+        let iovecs = buffers
+            .iter_mut()
+            .map(|buf| nix::libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut _,
+                iov_len: buf.len(),
+            })
+            .collect::<Vec<_>>();
+
+        unsafe { ring.submitter().register_buffers(&iovecs) }
+            .map_err(NetworkError::call("io_uring_register"))?;
+        ring.submitter()
+            .register_files(&[tun.tun_fd.as_raw_fd()])
+            .map_err(NetworkError::call("io_uring_register"))?;
+
+        let mut uring = Self {
+            ring,
+            buffers,
+            depth,
+            completed: VecDeque::new(),
+            reading: vec![false; depth],
+            free_writes: (depth..depth * 2).collect(),
+            tun,
+        };
+
+        for slot in 0..depth {
+            uring.submit_read(slot)?;
+        }
+        uring
+            .ring
+            .submit()
+            .map_err(NetworkError::call("io_uring_enter"))?;
+
+        Ok(uring)
+    }
+
+    pub fn tun(&self) -> &NetworkTun {
+        &self.tun
+    }
+
+    fn push(&mut self, entry: &squeue::Entry) -> Result<(), NetworkError> {
+        // The queue holds one entry per buffer, so it can only be full before a submit
+        while unsafe { self.ring.submission().push(entry) }.is_err() {
+            self.ring
+                .submit()
+                .map_err(NetworkError::call("io_uring_enter"))?;
+        }
+        Ok(())
+    }
+
+    fn submit_read(&mut self, slot: usize) -> Result<(), NetworkError> {
+        let buf = &mut self.buffers[slot];
+        let entry = opcode::ReadFixed::new(
+            types::Fixed(0),
+            buf.as_mut_ptr(),
+            buf.len() as u32,
+            slot as u16,
+        )
+        .build()
+        .user_data(slot as u64);
+
+        self.push(&entry)?;
+        self.reading[slot] = true;
+        Ok(())
+    }
+
+    /// Moves finished operations off the completion queue, waiting for at least `want`.
+    ///
+    /// Every completion is handled before the first failure is returned, so no read is left
+    /// without a resubmission and no write buffer is lost.
+    fn reap(&mut self, want: usize) -> Result<(), NetworkError> {
+        if want > 0 {
+            self.ring
+                .submit_and_wait(want)
+                .map_err(NetworkError::call("io_uring_enter"))?;
+        }
+
+        let mut result = Ok(());
+        let entries = self.ring.completion().collect::<Vec<cqueue::Entry>>();
+        for entry in entries {
+            let failed = |operation: &str, code: i32| {
+                Err(NetworkError::os(
+                    operation,
+                    Some(self.tun.name()),
+                    nix::errno::Errno::from_raw(-code),
+                ))
+            };
+
+            let code = entry.result();
+            let outcome = if entry.user_data() & WRITE_TAG != 0 {
+                self.free_writes
+                    .push((entry.user_data() & !WRITE_TAG) as usize);
+                match code < 0 {
+                    true => failed("io_uring write", code),
+                    false => Ok(()),
+                }
+            } else {
+                let slot = entry.user_data() as usize;
+                self.reading[slot] = false;
+                if code < 0 {
+                    let error = failed("io_uring read", code);
+                    self.submit_read(slot).and(error)
+                } else {
+                    self.completed.push_back((slot, code as usize));
+                    Ok(())
+                }
+            };
+            if result.is_ok() {
+                result = outcome;
+            }
+        }
+
+        result
+    }
+
+    /// Takes the next completed read, handing its buffer back to the kernel once `f` is done.
+    fn next_read<T>(&mut self, f: impl FnOnce(&[u8]) -> T) -> Result<T, NetworkError> {
+        while self.completed.is_empty() {
+            self.reap(1)?;
+        }
+
+        let (slot, n) = self.completed.pop_front().unwrap_or_default();
+        let buf = &self.buffers[slot];
+        let flags = u16::from_ne_bytes([buf[0], buf[1]]);
+        let value = if flags & consts::TUN_PKT_STRIP != 0 {
+            self.tun.counters.record_parse_error();
+            Err(NetworkError::PacketParseError(format!(
+                "packet was truncated to fit the buffer (capacity: {})",
+                buf.len() - PI_LEN
+            )))
+        } else {
+            let data = &buf[PI_LEN.min(n)..n];
+            self.tun.counters.record_received(data.len());
+            Ok(f(data))
+        };
+
+        self.submit_read(slot)?;
+        self.ring
+            .submit()
+            .map_err(NetworkError::call("io_uring_enter"))?;
+        value
+    }
+
+    pub fn recv(&mut self) -> Result<IpPacket, NetworkError> {
+        let packet = self.next_read(IpPacket::from_bytes)?;
+        packet.inspect_err(|_| self.tun.counters.record_parse_error())
+    }
+
+    /// Reads the next packet into a buffer from the tun's pool, without parsing it.
+    pub fn recv_pooled(&mut self) -> Result<PooledPacket, NetworkError> {
+        let pool = self.tun.pool();
+        self.next_read(|data| {
+            let mut packet = pool.get();
+            packet.fill(data).map(|_| packet)
+        })?
+    }
+
+    /// Reads a single frame as a list of packets, like [`NetworkTun::recv_segments`]. Virtio-net
+    /// headers aren't supported with io_uring, so there is always exactly one.
+    pub fn recv_segments(&mut self) -> Result<Vec<IpPacket>, NetworkError> {
+        self.recv().map(|packet| vec![packet])
+    }
+
+    /// Fills `bufs` with every packet that has already been read, waiting for at least one.
+    ///
+    /// Errors are only returned when no packet was filled, otherwise the batch ends early at the
+    /// packet that failed.
+    pub fn recv_batch(&mut self, bufs: &mut [PacketBuf]) -> Result<usize, NetworkError> {
+        if bufs.is_empty() {
+            return Ok(0);
+        }
+
+        self.reap(0)?;
+        let mut count = 0;
+        for buf in bufs.iter_mut() {
+            if count > 0 && self.completed.is_empty() {
+                break;
+            }
+            match self
+                .next_read(|data| buf.fill(data))
+                .and_then(|filled| filled)
+            {
+                Ok(()) => count += 1,
+                // Packets already read are more useful to the caller than the error
+                Err(_) if count > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn write_packet(&mut self, data: &[u8]) -> Result<(), NetworkError> {
+        let proto = frame_proto(data, self.tun.is_tap())?;
+
+        while self.free_writes.is_empty() {
+            self.reap(1)?;
+        }
+        let slot = self.free_writes.pop().unwrap_or_default();
+
+        let buf = &mut self.buffers[slot];
+        if PI_LEN + data.len() > buf.len() {
+            self.free_writes.push(slot);
+            return Err(NetworkError::PacketParseError(format!(
+                "packet does not fit in buffer (packet: {}, capacity: {})",
+                data.len(),
+                buf.len() - PI_LEN
+            )));
+        }
+        buf[0..2].fill(0);
+        buf[2..4].copy_from_slice(&proto.to_be_bytes());
+        buf[PI_LEN..PI_LEN + data.len()].copy_from_slice(data);
+
+        let entry = opcode::WriteFixed::new(
+            types::Fixed(0),
+            buf.as_ptr(),
+            (PI_LEN + data.len()) as u32,
+            slot as u16,
+        )
+        .build()
+        .user_data(WRITE_TAG | slot as u64);
+
+        if let Err(e) = self.push(&entry) {
+            self.free_writes.push(slot);
+            return Err(e);
+        }
+        self.ring
+            .submit()
+            .map_err(NetworkError::call("io_uring_enter"))?;
+        Ok(())
+    }
+
+    /// Queues `packet` for writing. Errors from earlier writes may be reported here.
+    pub fn send(&mut self, packet: &IpPacket) -> Result<(), NetworkError> {
+        self.write_packet(&packet.to_bytes())
+    }
+
+    pub fn send_pooled(&mut self, packet: &PooledPacket) -> Result<(), NetworkError> {
+        self.write_packet(packet.as_slice())
+    }
+
+    pub fn send_batch(&mut self, packets: &[PacketBuf]) -> Result<usize, NetworkError> {
+        for (i, packet) in packets.iter().enumerate() {
+            if let Err(e) = self.write_packet(packet.as_slice()) {
+                return if i == 0 { Err(e) } else { Ok(i) };
+            }
+        }
+
+        Ok(packets.len())
+    }
+
+    /// Gives up on the buffers when we can't tell whether the kernel is done with them.
+    fn leak_buffers(&mut self) {
+        std::mem::forget(std::mem::take(&mut self.buffers));
+    }
+
+    /// Waits until every queued write has completed.
+    pub fn flush(&mut self) -> Result<(), NetworkError> {
+        while self.free_writes.len() < self.depth {
+            self.reap(1)?;
+        }
+        Ok(())
+    }
+}
+
+// Cancels the outstanding reads and waits for every operation to finish, as the kernel keeps
+// using the buffers until then, even after the ring is closed
+impl Drop for UringTun {
+    fn drop(&mut self) {
+        let mut reads = (0..self.depth)
+            .filter(|&slot| self.reading[slot])
+            .collect::<Vec<_>>();
+        let mut writes = self.depth - self.free_writes.len();
+
+        for &slot in &reads {
+            let entry = opcode::AsyncCancel::new(slot as u64)
+                .build()
+                .user_data(CANCEL_TAG);
+            if self.push(&entry).is_err() {
+                return self.leak_buffers();
+            }
+        }
+
+        while !reads.is_empty() || writes > 0 {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.raw_os_error() == Some(nix::libc::EINTR) => continue,
+                Err(_) => return self.leak_buffers(),
+            }
+
+            for entry in self.ring.completion() {
+                let user_data = entry.user_data();
+                if user_data & CANCEL_TAG != 0 {
+                    continue;
+                }
+                if user_data & WRITE_TAG != 0 {
+                    writes -= 1;
+                } else {
+                    reads.retain(|&slot| slot as u64 != user_data);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use std::ops::DerefMut;
+
+use bitflags::bitflags;
+
+use super::{consts, error::NetworkError};
+
+bitflags! {
+    /// Offloads negotiated with `TUNSETOFFLOAD`, describing what we accept from the kernel.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct TunOffloads: u32 {
+        const CSUM = consts::TUN_F_CSUM as u32;
+        const TSO4 = consts::TUN_F_TSO4 as u32;
+        const TSO6 = consts::TUN_F_TSO6 as u32;
+        const TSO_ECN = consts::TUN_F_TSO_ECN as u32;
+        const USO4 = consts::TUN_F_USO4 as u32;
+        const USO6 = consts::TUN_F_USO6 as u32;
+    }
+}
+
+impl TunOffloads {
+    /// Everything we can segment in userspace. The GSO offloads all require `CSUM`.
+    pub const SEGMENTATION: Self = Self::CSUM
+        .union(Self::TSO4)
+        .union(Self::TSO6)
+        .union(Self::TSO_ECN)
+        .union(Self::USO4)
+        .union(Self::USO6);
+}
+
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+pub const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 2;
+
+pub const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+pub const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+pub const VIRTIO_NET_HDR_GSO_UDP: u8 = 3;
+pub const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+pub const VIRTIO_NET_HDR_GSO_UDP_L4: u8 = 5;
+pub const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/// `struct virtio_net_hdr`, which precedes every packet on a tun with `IFF_VNET_HDR` set.
+///
+/// Fields are in native byte order, which is what the kernel uses unless `TUNSETVNETLE` or
+/// `TUNSETVNETBE` is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VirtioNetHeader {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+impl VirtioNetHeader {
+    pub const SIZE: usize = 10;
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, NetworkError> {
+        if data.len() < Self::SIZE {
+            return Err(NetworkError::PacketParseError(format!(
+                "provided bytes are too short to be a virtio_net_hdr (<{} bytes)",
+                Self::SIZE
+            )));
+        }
+
+        let u16_at = |i: usize| u16::from_ne_bytes([data[i], data[i + 1]]);
+        Ok(Self {
+            flags: data[0],
+            gso_type: data[1],
+            hdr_len: u16_at(2),
+            gso_size: u16_at(4),
+            csum_start: u16_at(6),
+            csum_offset: u16_at(8),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut data = [0u8; Self::SIZE];
+        data[0] = self.flags;
+        data[1] = self.gso_type;
+        data[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        data[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        data[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        data[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+        data
+    }
+
+    /// Splits `packet` into MTU sized packets according to this header, completing any partial
+    /// checksums along the way. Packets without GSO are returned as is.
+    pub fn segment(&self, packet: &[u8]) -> Result<Vec<Vec<u8>>, NetworkError> {
+        let mut segments = vec![];
+        self.segment_into(packet, &mut segments, |headers, payload| {
+            Ok([headers, payload].concat())
+        })?;
+        Ok(segments)
+    }
+
+    /// Like [`VirtioNetHeader::segment`], but builds each segment in place in a buffer made by
+    /// `buffer` from the segment's headers and payload, so nothing is copied twice.
+    pub(crate) fn segment_into<B, F>(
+        &self,
+        packet: &[u8],
+        segments: &mut impl Extend<B>,
+        buffer: F,
+    ) -> Result<(), NetworkError>
+    where
+        B: DerefMut<Target = [u8]>,
+        F: FnMut(&[u8], &[u8]) -> Result<B, NetworkError>,
+    {
+        let mut buffer = buffer;
+        match self.gso_type & !VIRTIO_NET_HDR_GSO_ECN {
+            VIRTIO_NET_HDR_GSO_NONE => {
+                let mut packet = buffer(packet, &[])?;
+                if self.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0 {
+                    self.complete_checksum(&mut packet)?;
+                }
+                segments.extend([packet]);
+                Ok(())
+            }
+            VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_TCPV6 => segment_l4(
+                packet,
+                IPPROTO_TCP,
+                self.gso_size as usize,
+                segments,
+                buffer,
+            ),
+            VIRTIO_NET_HDR_GSO_UDP_L4 => segment_l4(
+                packet,
+                IPPROTO_UDP,
+                self.gso_size as usize,
+                segments,
+                buffer,
+            ),
+            VIRTIO_NET_HDR_GSO_UDP => Err(NetworkError::Unsupported(
+                "UFO packets require Ip fragmentation, which is not supported".to_string(),
+            )),
+            gso_type => Err(NetworkError::PacketParseError(format!(
+                "Unknown virtio_net_hdr gso type (got '{gso_type}')"
+            ))),
+        }
+    }
+
+    /// Finishes a partial checksum, where the kernel has only filled in the pseudo header sum.
+    fn complete_checksum(&self, packet: &mut [u8]) -> Result<(), NetworkError> {
+        let start = self.csum_start as usize;
+        let field = start + self.csum_offset as usize;
+        if field + 2 > packet.len() {
+            return Err(NetworkError::PacketParseError(format!(
+                "virtio_net_hdr checksum offset is out of bounds (start: {}, offset: {}, packet: {})",
+                self.csum_start,
+                self.csum_offset,
+                packet.len()
+            )));
+        }
+
+        let checksum = fold(sum(&packet[start..], 0));
+        packet[field..field + 2].copy_from_slice(&checksum.to_be_bytes());
+        Ok(())
+    }
+}
+
+fn sum(data: &[u8], initial: u32) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    let mut sum = chunks.by_ref().fold(initial, |acc, x| {
+        let acc = acc + u16::from_be_bytes([x[0], x[1]]) as u32;
+        (acc & 0xFFFF) + (acc >> 16)
+    });
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    sum
+}
+
+/// Folds a one's complement sum down to 16 bits and complements it.
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub(crate) fn ipv4_header_checksum(header: &[u8]) -> u16 {
+    let mut header = header.to_vec();
+    header[10] = 0;
+    header[11] = 0;
+    fold(sum(&header, 0))
+}
+
+fn pseudo_header_sum(packet: &[u8], protocol: u8, l4_len: usize) -> u32 {
+    if packet[0] >> 4 == 4 {
+        let sum = sum(&packet[12..20], 0);
+        sum + protocol as u32 + l4_len as u32
+    } else {
+        let sum = sum(&packet[8..40], 0);
+        sum + protocol as u32 + (l4_len as u32 >> 16) + (l4_len as u32 & 0xFFFF)
+    }
+}
+
+/// Splits a TCP or UDP super-packet into segments carrying at most `mss` bytes of payload.
+fn segment_l4<B, F>(
+    packet: &[u8],
+    protocol: u8,
+    mss: usize,
+    segments: &mut impl Extend<B>,
+    mut buffer: F,
+) -> Result<(), NetworkError>
+where
+    B: DerefMut<Target = [u8]>,
+    F: FnMut(&[u8], &[u8]) -> Result<B, NetworkError>,
+{
+    let too_short = || {
+        NetworkError::PacketParseError(
+            "provided bytes are too short to hold the GSO packet headers".to_string(),
+        )
+    };
+
+    if packet.is_empty() || mss == 0 {
+        return Err(too_short());
+    }
+
+    let is_ipv4 = packet[0] >> 4 == 4;
+    let (ip_len, next_header) = if is_ipv4 {
+        let ihl = (packet[0] & 0x0F) as usize;
+        if ihl < 5 {
+            return Err(NetworkError::PacketParseError(format!(
+                "Ipv4 header length is shorter than the minimum of 5 words (got '{ihl}')"
+            )));
+        }
+        (ihl * 4, *packet.get(9).ok_or_else(too_short)?)
+    } else {
+        (40, *packet.get(6).ok_or_else(too_short)?)
+    };
+
+    if next_header != protocol {
+        return Err(NetworkError::Unsupported(format!(
+            "GSO packets must directly carry protocol {protocol}, extension headers are not supported (got '{next_header}')"
+        )));
+    }
+
+    let l4_header_len = if protocol == IPPROTO_TCP {
+        let data_offset = (*packet.get(ip_len + 12).ok_or_else(too_short)? >> 4) as usize;
+        if data_offset < 5 {
+            return Err(NetworkError::PacketParseError(format!(
+                "TCP data offset is shorter than the minimum of 5 words (got '{data_offset}')"
+            )));
+        }
+        data_offset * 4
+    } else {
+        8
+    };
+    let header_len = ip_len + l4_header_len;
+    if packet.len() < header_len {
+        return Err(too_short());
+    }
+
+    let (headers, payload) = packet.split_at(header_len);
+    let identification = u16::from_be_bytes([headers[4], headers[5]]);
+    let sequence = u32::from_be_bytes([
+        headers[ip_len + 4],
+        headers[ip_len + 5],
+        headers[ip_len + 6],
+        headers[ip_len + 7],
+    ]);
+
+    let count = payload.len().div_ceil(mss).max(1);
+    for i in 0..count {
+        let chunk = &payload[(i * mss).min(payload.len())..((i + 1) * mss).min(payload.len())];
+
+        let mut segment = buffer(headers, chunk)?;
+
+        let total_len = segment.len();
+        let l4_len = total_len - ip_len;
+
+        if is_ipv4 {
+            segment[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+            let identification = identification.wrapping_add(i as u16);
+            segment[4..6].copy_from_slice(&identification.to_be_bytes());
+            let checksum = ipv4_header_checksum(&segment[..ip_len]);
+            segment[10..12].copy_from_slice(&checksum.to_be_bytes());
+        } else {
+            segment[4..6].copy_from_slice(&(l4_len as u16).to_be_bytes());
+        }
+
+        let checksum_field = if protocol == IPPROTO_TCP {
+            let sequence = sequence.wrapping_add((i * mss) as u32);
+            segment[ip_len + 4..ip_len + 8].copy_from_slice(&sequence.to_be_bytes());
+
+            // FIN and PSH only belong on the last segment, CWR only on the first
+            let tcp_flags = &mut segment[ip_len + 13];
+            if i + 1 != count {
+                *tcp_flags &= !0x09;
+            }
+            if i != 0 {
+                *tcp_flags &= !0x80;
+            }
+            ip_len + 16
+        } else {
+            segment[ip_len + 4..ip_len + 6].copy_from_slice(&(l4_len as u16).to_be_bytes());
+            ip_len + 6
+        };
+
+        segment[checksum_field] = 0;
+        segment[checksum_field + 1] = 0;
+        let pseudo = pseudo_header_sum(&segment, protocol, l4_len);
+        let mut checksum = fold(sum(&segment[ip_len..], pseudo));
+        if protocol == IPPROTO_UDP && checksum == 0 {
+            checksum = 0xFFFF;
+        }
+        segment[checksum_field..checksum_field + 2].copy_from_slice(&checksum.to_be_bytes());
+
+        segments.extend([segment]);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp_ipv4(payload_len: usize, tcp_flags: u8) -> Vec<u8> {
+        let total_len = (40 + payload_len) as u16;
+        let mut packet = vec![0x45, 0];
+        packet.extend(total_len.to_be_bytes());
+        packet.extend([0x12, 0x34, 0x40, 0, 64, IPPROTO_TCP, 0, 0]);
+        packet.extend([10, 0, 0, 1, 10, 0, 0, 2]);
+        // Ports, sequence number 1000, no ack, data offset 5
+        packet.extend([0x1F, 0x90, 0xC3, 0x50, 0, 0, 0x03, 0xE8, 0, 0, 0, 0]);
+        packet.extend([0x50, tcp_flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+        packet.extend((0..payload_len).map(|i| i as u8));
+        packet
+    }
+
+    fn udp_ipv6(payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend(((8 + payload_len) as u16).to_be_bytes());
+        packet.extend([IPPROTO_UDP, 64]);
+        packet.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        packet.extend([0xFD; 16]);
+        packet.extend([0x13, 0x88, 0x13, 0x89, 0, 0, 0, 0]);
+        packet.extend((0..payload_len).map(|i| i as u8));
+        packet
+    }
+
+    fn gso(gso_type: u8, gso_size: u16) -> VirtioNetHeader {
+        VirtioNetHeader {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type,
+            gso_size,
+            ..Default::default()
+        }
+    }
+
+    /// Verifying a checksum sums to zero once the checksum field itself is included.
+    fn verifies(data: &[u8], initial: u32) -> bool {
+        fold(sum(data, initial)) == 0
+    }
+
+    #[test]
+    fn header_round_trips_through_bytes() {
+        let header = VirtioNetHeader {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: VIRTIO_NET_HDR_GSO_TCPV4,
+            hdr_len: 54,
+            gso_size: 1448,
+            csum_start: 34,
+            csum_offset: 16,
+        };
+        assert_eq!(
+            VirtioNetHeader::from_bytes(&header.to_bytes()).unwrap(),
+            header
+        );
+        assert!(VirtioNetHeader::from_bytes(&[0; 9]).is_err());
+    }
+
+    #[test]
+    fn tcp_segments_carry_mss_payload_and_valid_checksums() {
+        let packet = tcp_ipv4(2500, 0x19);
+        let segments = gso(VIRTIO_NET_HDR_GSO_TCPV4, 1000)
+            .segment(&packet)
+            .unwrap();
+
+        let lengths = segments.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths, [1040, 1040, 540]);
+        for (i, segment) in segments.iter().enumerate() {
+            let total_len = u16::from_be_bytes([segment[2], segment[3]]) as usize;
+            assert_eq!(total_len, segment.len());
+            assert_eq!(
+                u16::from_be_bytes([segment[4], segment[5]]),
+                0x1234 + i as u16
+            );
+            let sequence = u32::from_be_bytes(segment[24..28].try_into().unwrap());
+            assert_eq!(sequence, 1000 + 1000 * i as u32);
+            assert_eq!(
+                &segment[40..],
+                &packet[40 + 1000 * i..(40 + 1000 * (i + 1)).min(2540)]
+            );
+
+            assert!(verifies(&segment[..20], 0));
+            let pseudo = pseudo_header_sum(segment, IPPROTO_TCP, segment.len() - 20);
+            assert!(verifies(&segment[20..], pseudo));
+        }
+        // FIN and PSH stay on the last segment only
+        assert_eq!(segments[0][33], 0x10);
+        assert_eq!(segments[1][33], 0x10);
+        assert_eq!(segments[2][33], 0x19);
+    }
+
+    #[test]
+    fn udp_segments_get_their_own_lengths() {
+        let packet = udp_ipv6(2100);
+        let segments = gso(VIRTIO_NET_HDR_GSO_UDP_L4, 1000)
+            .segment(&packet)
+            .unwrap();
+
+        let lengths = segments.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths, [1048, 1048, 148]);
+        for segment in &segments {
+            let l4_len = segment.len() - 40;
+            assert_eq!(
+                u16::from_be_bytes([segment[4], segment[5]]) as usize,
+                l4_len
+            );
+            assert_eq!(
+                u16::from_be_bytes([segment[44], segment[45]]) as usize,
+                l4_len
+            );
+            let pseudo = pseudo_header_sum(segment, IPPROTO_UDP, l4_len);
+            assert!(verifies(&segment[40..], pseudo));
+        }
+    }
+
+    #[test]
+    fn packets_without_gso_only_get_their_checksum_completed() {
+        let packet = tcp_ipv4(100, 0x18);
+        let header = VirtioNetHeader {
+            csum_start: 20,
+            csum_offset: 16,
+            ..gso(VIRTIO_NET_HDR_GSO_NONE, 0)
+        };
+        let segments = header.segment(&packet).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].len(), packet.len());
+        assert!(verifies(&segments[0][20..], 0));
+
+        let header = VirtioNetHeader {
+            csum_offset: 200,
+            ..header
+        };
+        assert!(header.segment(&packet).is_err());
+    }
+
+    #[test]
+    fn short_header_lengths_are_rejected() {
+        let mut packet = tcp_ipv4(100, 0);
+        packet[0] = 0x44;
+        assert!(matches!(
+            gso(VIRTIO_NET_HDR_GSO_TCPV4, 50).segment(&packet),
+            Err(NetworkError::PacketParseError(_))
+        ));
+
+        let mut packet = tcp_ipv4(100, 0);
+        packet[32] = 0x40;
+        assert!(matches!(
+            gso(VIRTIO_NET_HDR_GSO_TCPV4, 50).segment(&packet),
+            Err(NetworkError::PacketParseError(_))
+        ));
+
+        let packet = tcp_ipv4(100, 0);
+        assert!(gso(VIRTIO_NET_HDR_GSO_TCPV4, 50)
+            .segment(&packet[..30])
+            .is_err());
+        assert!(gso(VIRTIO_NET_HDR_GSO_TCPV4, 0).segment(&packet).is_err());
+    }
+}